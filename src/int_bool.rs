@@ -1,8 +1,80 @@
-#[derive(Debug,Clone)]
+use std::rc::Rc;
+use std::fmt;
+use std::collections::BTreeMap;
+use let_lang_exp::*;
+use let_lang_env::*;
+use let_lang_class::*;
+use let_lang_interp::{Continuation, Generator, Guarded};
+
+#[derive(Debug,Clone,PartialEq)]
 
 pub enum IntBool {
     Integer(i32),
     Boolean(bool),
+    Str(String),
+    List(Rc<Vec<IntBool>>),
+    Dict(Rc<BTreeMap<DictKey, IntBool>>),  // persistent: updates build a new map
+    Proc(Rc<Proc>),
+    Object(Rc<Object>),
+    Cont(Rc<Continuation>),  // captured by perform, resumed by calling it
+    Generator(Rc<Generator>),
+    Code(Rc<LetLangExp>),    // made by quote, evaluated by run
+    Guarded(Rc<Guarded>),    // a procedure under an arrow contract
+}
+
+// a closure: parameters, body and the environment the procedure was created in
+#[derive(Clone)]
+pub struct Proc {
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    pub body: Rc<LetLangExp>,
+    pub env: LetLangEnv,
+}
+
+impl Proc {
+    pub fn new_proc(name: Option<&String>, params: &Vec<String>, body: &Rc<LetLangExp>, env: &LetLangEnv) -> Self {
+        Proc { name: name.cloned(), params: params.clone(), body: body.clone(), env: env.clone() }
+    }
+    pub fn to_string(&self) -> String {
+        match self.name {
+            Some(ref s) => format!("<procedure {}>", s),
+            None        => "<procedure>".to_string(),
+        }}
+}
+
+// procedures are equal only to themselves
+impl PartialEq for Proc {
+    fn eq(&self, other: &Proc) -> bool {
+        self as *const Proc == other as *const Proc
+    }}
+impl fmt::Debug for Proc { // the saved environment is too large to print usefully
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_string())
+    }}
+
+// the values allowed as dictionary keys; Ord keeps dict printing and equality deterministic
+#[derive(Debug,Clone,PartialEq,Eq,PartialOrd,Ord)]
+pub enum DictKey {
+    Integer(i32),
+    Boolean(bool),
+    Str(String),
+}
+
+impl DictKey {
+    // returns None for values that cannot be used as keys
+    pub fn from_val(val: &IntBool) -> Option<DictKey> {
+        match *val {
+            IntBool::Integer(i)  => Some(DictKey::Integer(i)),
+            IntBool::Boolean(b)  => Some(DictKey::Boolean(b)),
+            IntBool::Str(ref s)  => Some(DictKey::Str(s.clone())),
+            _                    => None,
+        }}
+    pub fn to_val(&self) -> IntBool {
+        match *self {
+            DictKey::Integer(i)  => IntBool::Integer(i),
+            DictKey::Boolean(b)  => IntBool::Boolean(b),
+            DictKey::Str(ref s)  => IntBool::Str(s.clone()),
+        }}
 }
 
 impl IntBool {
@@ -10,6 +82,44 @@ impl IntBool {
         match *self {
             IntBool::Integer(i) => i.to_string(),
             IntBool::Boolean(b) => b.to_string(),
+            IntBool::Str(ref s) => quote_str(s),
+            IntBool::List(ref vals) => {let mut temp = "[".to_string();
+                                        for (i, v) in vals.iter().enumerate() {
+                                            if i > 0 { temp.push_str(", ") };
+                                            temp.push_str(&(v.to_string()));
+                                        }
+                                        temp.push_str("]");
+                                        temp},
+            IntBool::Dict(ref map) => {let mut temp = "{".to_string();
+                                       for (i, (k, v)) in map.iter().enumerate() {
+                                           if i > 0 { temp.push_str(", ") };
+                                           temp.push_str(&(k.to_val().to_string()));
+                                           temp.push_str(": ");
+                                           temp.push_str(&(v.to_string()));
+                                       }
+                                       temp.push_str("}");
+                                       temp},
+            IntBool::Proc(ref p) => p.to_string(),
+            IntBool::Object(ref o) => format!("<object {}>", o.class.name),
+            IntBool::Cont(_) => "<continuation>".to_string(),
+            IntBool::Generator(_) => "<generator>".to_string(),
+            IntBool::Code(ref e) => format!("quote {}", e),
+            IntBool::Guarded(ref g) => g.to_string(),
         }
     }
-}
\ No newline at end of file
+}
+
+// string literal syntax, shared by values and the Display of StrExp
+pub fn quote_str(s: &str) -> String {
+    let mut temp = "\"".to_string();
+    for c in s.chars() {
+        match c {
+            '"'  => temp.push_str("\\\""),
+            '\\' => temp.push_str("\\\\"),
+            '\n' => temp.push_str("\\n"),
+            _    => temp.push(c),
+        }
+    }
+    temp.push('"');
+    temp
+}
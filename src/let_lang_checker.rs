@@ -0,0 +1,930 @@
+// Static checker for LET programs.
+// Checking is a separate pass run before value_of_program. Dictionary lookups
+// and unannotated proc parameters have type any, which is compatible with
+// every type, so untyped programs still check wherever their uses agree.
+
+use std::rc::Rc;
+use std::fmt;
+use std::collections::BTreeMap;
+
+use let_lang_exp::*;
+use let_lang_type::*;
+use let_lang_class::append_field_names;
+use let_lang_scanner::Span;
+
+// span is that of the innermost expression being checked, if any
+pub struct TypeErr {
+    message: String,
+    span: Option<Span>,
+}
+
+impl TypeErr {
+    fn at(self, span: Span) -> TypeErr {
+        TypeErr { span: self.span.or(Some(span)), ..self }
+    }
+}
+
+impl fmt::Display for TypeErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(ref span) => write!(f, "TypeError: {} ({})", self.message, span),
+            None           => write!(f, "TypeError: {}", self.message),
+        }
+    }}
+impl fmt::Debug for TypeErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }}
+
+macro_rules! type_err {
+    ($($arg:tt)*) => (
+        return Err(TypeErr { message: format!($($arg)*), span: None })
+    )
+}
+
+// type environment; a module is bound to its interface as clients see it,
+// and ExtendTenvType binds a type name inside a module body or interface
+#[derive(Debug,Clone)]
+pub enum TypeEnv {
+    EmptyTenv,
+    ExtendTenv(String, Type, Rc<TypeEnv>),
+    ExtendTenvType(String, Type, Rc<TypeEnv>),
+    ExtendTenvModule(String, Rc<ModuleIface>, Rc<TypeEnv>),
+    ExtendTenvClass(String, Rc<StaticClass>, Rc<TypeEnv>),
+}
+
+// an interface with every type expanded; an opaque type t of module m
+// appears as QualifiedType(m, t), which is equal only to itself
+#[derive(Debug,Clone)]
+pub struct ModuleIface {
+    pub types: Vec<(String, Type)>,
+    pub values: Vec<(String, Type)>,
+}
+
+// a class or interface as the checker sees it; methods maps every method,
+// inherited ones included, to its signature as a proc type
+#[derive(Debug,Clone)]
+pub struct StaticClass {
+    pub name: String,
+    pub is_interface: bool,
+    pub ancestors: Rc<Vec<String>>,
+    pub field_names: Vec<String>,
+    pub field_types: Vec<Type>,
+    pub methods: BTreeMap<String, Type>,
+}
+
+impl StaticClass {
+    fn new_object_class() -> Self {
+        StaticClass { name: "object".to_string(), is_interface: false, ancestors: Rc::new(vec!["object".to_string()]),
+                      field_names: Vec::new(), field_types: Vec::new(), methods: BTreeMap::new() }
+    }
+    pub fn class_type(&self) -> Type {
+        Type::ClassType(self.name.clone(), self.ancestors.clone())
+    }
+    fn kind(&self) -> &'static str {
+        if self.is_interface { "interface" } else { "class" }
+    }
+}
+
+impl TypeEnv {
+    pub fn new_tenv() -> Self {
+        TypeEnv::EmptyTenv
+    }
+    pub fn extend_tenv(&self, s: &String, t: &Type) -> Self {
+        TypeEnv::ExtendTenv(s.clone(), t.clone(), Rc::new(self.clone()))
+    }
+    pub fn extend_tenv_type(&self, s: &String, t: &Type) -> Self {
+        TypeEnv::ExtendTenvType(s.clone(), t.clone(), Rc::new(self.clone()))
+    }
+    pub fn extend_tenv_module(&self, m: &String, iface: &ModuleIface) -> Self {
+        TypeEnv::ExtendTenvModule(m.clone(), Rc::new(iface.clone()), Rc::new(self.clone()))
+    }
+    pub fn extend_tenv_class(&self, c: &String, class: &StaticClass) -> Self {
+        TypeEnv::ExtendTenvClass(c.clone(), Rc::new(class.clone()), Rc::new(self.clone()))
+    }
+    pub fn apply_tenv(&self, s: &String) -> Option<Type> {
+        match *self {
+            TypeEnv::ExtendTenv(ref var, ref t, ref tenv) =>
+                if s[..] == var[..] { Some(t.clone()) } else { tenv.apply_tenv(s) },
+            TypeEnv::ExtendTenvType(_, _, ref tenv)   => tenv.apply_tenv(s),
+            TypeEnv::ExtendTenvModule(_, _, ref tenv) => tenv.apply_tenv(s),
+            TypeEnv::ExtendTenvClass(_, _, ref tenv)  => tenv.apply_tenv(s),
+            TypeEnv::EmptyTenv => None,
+        }}
+    pub fn apply_tenv_type(&self, s: &String) -> Option<Type> {
+        match *self {
+            TypeEnv::ExtendTenvType(ref name, ref t, ref tenv) =>
+                if s[..] == name[..] { Some(t.clone()) } else { tenv.apply_tenv_type(s) },
+            TypeEnv::ExtendTenv(_, _, ref tenv)       => tenv.apply_tenv_type(s),
+            TypeEnv::ExtendTenvModule(_, _, ref tenv) => tenv.apply_tenv_type(s),
+            TypeEnv::ExtendTenvClass(_, _, ref tenv)  => tenv.apply_tenv_type(s),
+            TypeEnv::EmptyTenv => None,
+        }}
+    pub fn lookup_module(&self, m: &String) -> Option<Rc<ModuleIface>> {
+        match *self {
+            TypeEnv::ExtendTenvModule(ref name, ref iface, ref tenv) =>
+                if m[..] == name[..] { Some(iface.clone()) } else { tenv.lookup_module(m) },
+            TypeEnv::ExtendTenv(_, _, ref tenv)      => tenv.lookup_module(m),
+            TypeEnv::ExtendTenvType(_, _, ref tenv)  => tenv.lookup_module(m),
+            TypeEnv::ExtendTenvClass(_, _, ref tenv) => tenv.lookup_module(m),
+            TypeEnv::EmptyTenv => None,
+        }}
+    // the class object is always in scope
+    pub fn lookup_class(&self, c: &String) -> Option<Rc<StaticClass>> {
+        match *self {
+            TypeEnv::ExtendTenvClass(ref name, ref class, ref tenv) =>
+                if c[..] == name[..] { Some(class.clone()) } else { tenv.lookup_class(c) },
+            TypeEnv::ExtendTenv(_, _, ref tenv)       => tenv.lookup_class(c),
+            TypeEnv::ExtendTenvType(_, _, ref tenv)   => tenv.lookup_class(c),
+            TypeEnv::ExtendTenvModule(_, _, ref tenv) => tenv.lookup_class(c),
+            TypeEnv::EmptyTenv if c == "object"       => Some(Rc::new(StaticClass::new_object_class())),
+            TypeEnv::EmptyTenv => None,
+        }}
+}
+
+// replaces type names by what they stand for in tenv; a class or interface
+// name stands for its class type
+pub fn expand_type(t: &Type, tenv: &TypeEnv) -> Result<Type, TypeErr> {
+    match *t {
+        Type::NamedType(ref s) => match tenv.apply_tenv_type(s) {
+            Some(t) => Ok(t),
+            None    => match tenv.lookup_class(s) {
+                           Some(class) => Ok(class.class_type()),
+                           None        => type_err!("Unknown type: {}", s),
+                           },
+            },
+        Type::QualifiedType(ref m, ref s) => {
+            let iface = match tenv.lookup_module(m) {
+                            Some(iface) => iface,
+                            None        => type_err!("Unknown module: {}", m),
+                            };
+            match iface.types.iter().find(|&&(ref name, _)| name == s) {
+                Some(&(_, ref t)) => Ok(t.clone()),
+                None              => type_err!("Module {} does not export a type {}", m, s),
+            }},
+        Type::ProcType(ref args, ref result) => {
+            let mut arg_types = Vec::new();
+            for arg in args.iter() {
+                arg_types.push(expand_type(arg, tenv)?);
+            }
+            Ok(Type::new_proc_type(&arg_types, &expand_type(result, tenv)?))},
+        _ => Ok(t.clone()),
+    }}
+
+// checks every definition and returns the type of the main expression
+// (of the last define if there is none)
+pub fn type_of_program(pgm: &Program, tenv: &TypeEnv) -> Result<Type, TypeErr> {
+    let mut pgm_tenv = tenv.clone();
+    let mut last_type = Type::AnyType;
+    for def in pgm.defs.iter() {
+        match *def {
+            Definition::ValDefn(ref s, ref e) => {
+                let t = type_of(e, &pgm_tenv)?;
+                pgm_tenv = pgm_tenv.extend_tenv(s, &t);
+                last_type = t;
+            },
+            Definition::ModuleDefn(ref m) => {
+                let iface = check_module(m, &pgm_tenv)?;
+                pgm_tenv = pgm_tenv.extend_tenv_module(&m.name, &iface);
+            },
+            Definition::ClassDefn(ref c) => {
+                let class = check_class(c, &pgm_tenv)?;
+                pgm_tenv = pgm_tenv.extend_tenv_class(&c.name, &class);
+            },
+            Definition::InterfaceDefn(ref i) => {
+                let iface = check_interface(i, &pgm_tenv)?;
+                pgm_tenv = pgm_tenv.extend_tenv_class(&i.name, &iface);
+            },
+        }
+    }
+    match pgm.body {
+        Some(ref e) => type_of(e, &pgm_tenv),
+        None        => Ok(last_type),
+    }}
+
+// the body must define every type and bind every name in the interface at a
+// compatible type; inside the body an opaque type is its definition, but
+// clients get the interface with opaque types left abstract
+fn check_module(m: &ModuleDefn, tenv: &TypeEnv) -> Result<ModuleIface, TypeErr> {
+    let mut body_tenv = tenv.clone();
+    for &(ref t, ref defn) in m.body_types.iter() {
+        let expanded = expand_type(defn, &body_tenv)?;
+        body_tenv = body_tenv.extend_tenv_type(t, &expanded);
+    }
+    for &(ref s, ref e) in m.body.iter() {
+        let t = type_of(e, &body_tenv)?;
+        body_tenv = body_tenv.extend_tenv(s, &t);
+    }
+    for &(ref t, ref decl) in m.iface_types.iter() {
+        if !m.body_types.iter().any(|&(ref name, _)| name == t) {
+            type_err!("module {}: body does not define type {}, which its interface declares", m.name, t);
+        }
+        if let Some(ref decl) = *decl {
+            let expected = expand_type(decl, &body_tenv)?;
+            let actual = body_tenv.apply_tenv_type(t).unwrap();
+            if expected != actual {
+                type_err!("module {}: type {} is {} in the body but the interface declares transparent {} = {}",
+                          m.name, t, actual, t, expected);
+            }
+        }
+    }
+    for &(ref s, ref decl) in m.interface.iter() {
+        let expected = expand_type(decl, &body_tenv)?;
+        if !m.body.iter().any(|&(ref v, _)| v == s) {
+            type_err!("module {}: body does not define {}, which its interface declares as {}",
+                      m.name, s, decl);
+        }
+        let actual = body_tenv.apply_tenv(s).unwrap();
+        if !Type::is_compatible(&expected, &actual) {
+            type_err!("module {}: {} has type {} in the body but the interface declares {}",
+                      m.name, s, actual, decl);
+        }
+    }
+    let mut client_tenv = tenv.clone();
+    let mut types = Vec::new();
+    for &(ref t, ref decl) in m.iface_types.iter() {
+        let client_type = match *decl {
+                              Some(ref decl) => expand_type(decl, &client_tenv)?,
+                              None           => Type::QualifiedType(m.name.clone(), t.clone()),
+                              };
+        client_tenv = client_tenv.extend_tenv_type(t, &client_type);
+        types.push((t.clone(), client_type));
+    }
+    let mut values = Vec::new();
+    for &(ref s, ref decl) in m.interface.iter() {
+        values.push((s.clone(), expand_type(decl, &client_tenv)?));
+    }
+    Ok(ModuleIface { types: types, values: values })
+}
+
+// a class must be declared after its superclass and the interfaces it
+// implements, but may mention itself in its field and method types.
+// A method overriding an inherited one, other than initialize, must have a
+// compatible signature, and the class must have every method of its
+// interfaces. Fields, parameters and results without a type are any
+fn check_class(c: &ClassDecl, tenv: &TypeEnv) -> Result<StaticClass, TypeErr> {
+    let super_class = match tenv.lookup_class(&c.super_name) {
+                          Some(ref s) if s.is_interface => type_err!("class {} cannot extend interface {}",
+                                                                     c.name, c.super_name),
+                          Some(s)                      => s,
+                          None                         => type_err!("class {} extends unknown class {}",
+                                                                    c.name, c.super_name),
+                          };
+    let mut ancestors = vec![c.name.clone()];
+    ancestors.extend(super_class.ancestors.iter().cloned());
+    let mut ifaces = Vec::new();
+    for i in c.interfaces.iter() {
+        match tenv.lookup_class(i) {
+            Some(ref iface) if iface.is_interface => ifaces.push(iface.clone()),
+            Some(_) => type_err!("class {} implements {}, which is a class, not an interface", c.name, i),
+            None    => type_err!("class {} implements unknown interface {}", c.name, i),
+        }
+        ancestors.push(i.clone());
+    }
+    let mut class = StaticClass { name: c.name.clone(), is_interface: false, ancestors: Rc::new(ancestors),
+                                  field_names: Vec::new(), field_types: Vec::new(),
+                                  methods: super_class.methods.clone() };
+    let header_tenv = tenv.extend_tenv_class(&c.name, &class);
+    let own_fields: Vec<String> = c.fields.iter().map(|&(ref f, _)| f.clone()).collect();
+    class.field_names = append_field_names(&super_class.field_names, &own_fields);
+    class.field_types = super_class.field_types.clone();
+    for &(_, ref t) in c.fields.iter() {
+        class.field_types.push(expand_annotation(t, &header_tenv)?);
+    }
+    for m in c.methods.iter() {
+        let sig = method_type(&m.sig, &header_tenv)?;
+        if let Some(inherited) = super_class.methods.get(&m.sig.name) {
+            if m.sig.name != "initialize" && !Type::is_compatible(inherited, &sig) {
+                type_err!("class {}: method {} has signature {} but overrides method {} of class {} with signature {}",
+                          c.name, m.sig.name, sig, m.sig.name, c.super_name, inherited);
+            }
+        }
+        class.methods.insert(m.sig.name.clone(), sig);
+    }
+    for iface in ifaces.iter() {
+        for (m, expected) in iface.methods.iter() {
+            match class.methods.get(m) {
+                Some(actual) => if !Type::is_compatible(expected, actual) {
+                                    type_err!("class {}: method {} has signature {} but interface {} expects {}",
+                                              c.name, m, actual, iface.name, expected);
+                                },
+                None         => type_err!("class {} does not implement method {} of interface {}, expected signature {}",
+                                          c.name, m, iface.name, expected),
+            }
+        }
+    }
+    let class_tenv = tenv.extend_tenv_class(&c.name, &class);
+    let mut fields_tenv = class_tenv.extend_tenv(&("self".to_string()), &class.class_type())
+                                    .extend_tenv(&("%super".to_string()), &super_class.class_type());
+    for (f, t) in class.field_names.iter().zip(class.field_types.iter()) {
+        fields_tenv = fields_tenv.extend_tenv(f, t);
+    }
+    for m in c.methods.iter() {
+        let mut method_tenv = fields_tenv.clone();
+        for &(ref p, ref t) in m.sig.params.iter() {
+            method_tenv = method_tenv.extend_tenv(p, &expand_annotation(t, &class_tenv)?);
+        }
+        let body_type = type_of(&m.body, &method_tenv)?;
+        let expected = expand_annotation(&m.sig.result, &class_tenv)?;
+        if !Type::is_compatible(&expected, &body_type) {
+            type_err!("class {}: method {} should return {} but its body has type {}",
+                      c.name, m.sig.name, expected, body_type);
+        }
+    }
+    Ok(class)
+}
+
+// an interface may mention itself in its method signatures
+fn check_interface(i: &InterfaceDecl, tenv: &TypeEnv) -> Result<StaticClass, TypeErr> {
+    let mut iface = StaticClass { name: i.name.clone(), is_interface: true, ancestors: Rc::new(vec![i.name.clone()]),
+                                  field_names: Vec::new(), field_types: Vec::new(), methods: BTreeMap::new() };
+    let header_tenv = tenv.extend_tenv_class(&i.name, &iface);
+    for m in i.methods.iter() {
+        let sig = method_type(m, &header_tenv)?;
+        iface.methods.insert(m.name.clone(), sig);
+    }
+    Ok(iface)
+}
+
+fn method_type(sig: &MethodSig, tenv: &TypeEnv) -> Result<Type, TypeErr> {
+    let mut arg_types = Vec::new();
+    for &(_, ref t) in sig.params.iter() {
+        arg_types.push(expand_annotation(t, tenv)?);
+    }
+    Ok(Type::new_proc_type(&arg_types, &expand_annotation(&sig.result, tenv)?))
+}
+
+// a missing annotation means any
+fn expand_annotation(t: &Option<Type>, tenv: &TypeEnv) -> Result<Type, TypeErr> {
+    match *t {
+        Some(ref t) => expand_type(t, tenv),
+        None        => Ok(Type::AnyType),
+    }}
+
+pub fn type_of(exp: &LetLangExp, tenv: &TypeEnv) -> Result<Type, TypeErr> {
+    match *exp {
+        LetLangExp::ConstExp(_)      => Ok(Type::IntType),
+        LetLangExp::Boolean(_)       => Ok(Type::BoolType),
+        LetLangExp::StrExp(_)        => Ok(Type::StrType),
+        LetLangExp::VarExp(ref s)    => match tenv.apply_tenv(s) {
+                                            Some(t) => Ok(t),
+                                            None    => type_err!("Unbound variable: {}", s),
+                                            },
+        LetLangExp::DiffExp(ref e1, ref e2) => {
+            expect_type(e1, &Type::IntType, tenv, "first operand of -")?;
+            expect_type(e2, &Type::IntType, tenv, "second operand of -")?;
+            Ok(Type::IntType)},
+        LetLangExp::IsZeroExp(ref e) => {
+            expect_type(e, &Type::IntType, tenv, "operand of iszero")?;
+            Ok(Type::BoolType)},
+        LetLangExp::IfExp(ref e1, ref e2, ref e3) => {
+            expect_type(e1, &Type::BoolType, tenv, "test of if")?;
+            let t2 = type_of(e2, tenv)?;
+            let t3 = type_of(e3, tenv)?;
+            match Type::join(&t2, &t3) {
+                Some(t) => Ok(t),
+                None    => type_err!("Branches of if have different types: {} and {} in {}", t2, t3, exp),
+            }},
+        LetLangExp::LetExp(ref s, ref e1, ref e2) => {
+            let t1 = type_of(e1, tenv)?;
+            type_of(e2, &tenv.extend_tenv(s, &t1))},
+        LetLangExp::SpannedExp(span, ref e) => type_of(e, tenv).map_err(|err| err.at(span)),
+        LetLangExp::ContractLetExp(ref s, ref c, ref e1, ref e2) => {
+            let t1 = type_of(e1, tenv)?;
+            check_contract(c, &t1, tenv, exp)?;
+            type_of(e2, &tenv.extend_tenv(s, &t1))},
+        LetLangExp::ErrorExp => type_err!("syntax error"),
+        LetLangExp::AssertExp(ref test, _) => {
+            expect_type(test, &Type::BoolType, tenv, "test of assert")?;
+            Ok(Type::BoolType)},
+        LetLangExp::DictExp(ref entries) => {
+            for &(ref k, ref v) in entries.iter() {
+                expect_key(k, tenv)?;
+                expect_any(v, tenv, "value of a dictionary")?;
+            }
+            Ok(Type::DictType)},
+        LetLangExp::DictGetExp(ref d, ref k) => {
+            expect_type(d, &Type::DictType, tenv, "dictionary of dict-get")?;
+            expect_key(k, tenv)?;
+            Ok(Type::AnyType)},
+        LetLangExp::DictSetExp(ref d, ref k, ref v) => {
+            expect_type(d, &Type::DictType, tenv, "dictionary of dict-set")?;
+            expect_key(k, tenv)?;
+            expect_any(v, tenv, "value of dict-set")?;
+            Ok(Type::DictType)},
+        LetLangExp::DictHasExp(ref d, ref k) => {
+            expect_type(d, &Type::DictType, tenv, "dictionary of dict-has?")?;
+            expect_key(k, tenv)?;
+            Ok(Type::BoolType)},
+        LetLangExp::DictRemoveExp(ref d, ref k) => {
+            expect_type(d, &Type::DictType, tenv, "dictionary of dict-remove")?;
+            expect_key(k, tenv)?;
+            Ok(Type::DictType)},
+        LetLangExp::DictKeysExp(ref d) => {
+            expect_type(d, &Type::DictType, tenv, "dictionary of dict-keys")?;
+            Ok(Type::ListType)},
+        LetLangExp::BeginExp(ref exps) => {
+            let mut last_type = Type::AnyType;
+            for e in exps.iter() {
+                last_type = type_of(e, tenv)?;
+            }
+            Ok(last_type)},
+        // parameters take the types of their initial values; calls to the loop
+        // inside its own body have type any
+        LetLangExp::NamedLetExp(ref name, ref bindings, ref body) => {
+            let mut param_types = Vec::new();
+            for &(_, ref e) in bindings.iter() {
+                param_types.push(type_of(e, tenv)?);
+            }
+            let mut body_tenv = tenv.extend_tenv(name, &Type::new_proc_type(&param_types, &Type::AnyType));
+            for (&(ref v, _), t) in bindings.iter().zip(param_types.iter()) {
+                body_tenv = body_tenv.extend_tenv(v, t);
+            }
+            type_of(body, &body_tenv)},
+        LetLangExp::CallExp(ref rator, ref rands) => {
+            let rator_type = type_of(rator, tenv)?;
+            let mut rand_types = Vec::new();
+            for e in rands.iter() {
+                rand_types.push(type_of(e, tenv)?);
+            }
+            match rator_type {
+                Type::AnyType => Ok(Type::AnyType),
+                Type::ProcType(ref arg_types, ref result) => {
+                    if arg_types.len() != rand_types.len() {
+                        type_err!("{} expects {} arguments but is given {} in {}",
+                                  rator, arg_types.len(), rand_types.len(), exp);
+                    }
+                    for (i, (expected, actual)) in arg_types.iter().zip(rand_types.iter()).enumerate() {
+                        if !Type::is_compatible(expected, actual) {
+                            type_err!("Argument {} of {} should have type {} but has type {}",
+                                      i + 1, rator, expected, actual);
+                        }
+                    }
+                    Ok((**result).clone())},
+                t => type_err!("{} has type {} and cannot be called", rator, t),
+            }},
+        LetLangExp::WhileExp(ref test, ref body) => {
+            expect_type(test, &Type::BoolType, tenv, "test of while")?;
+            type_of(body, tenv)?;
+            Ok(Type::BoolType)},
+        LetLangExp::SetExp(ref s, ref e) => {
+            let var_type = match tenv.apply_tenv(s) {
+                               Some(t) => t,
+                               None    => type_err!("Unbound variable: {}", s),
+                               };
+            expect_type(e, &var_type, tenv, &format!("value assigned to {}", s))?;
+            Ok(var_type)},
+        LetLangExp::ProcExp(ref params, ref body) => {
+            let mut body_tenv = tenv.clone();
+            let mut arg_types = Vec::new();
+            for &(ref v, ref t) in params.iter() {
+                let arg_type = match *t {
+                                   Some(ref t) => expand_type(t, tenv)?,
+                                   None        => Type::AnyType,
+                                   };
+                body_tenv = body_tenv.extend_tenv(v, &arg_type);
+                arg_types.push(arg_type);
+            }
+            let result = type_of(body, &body_tenv)?;
+            Ok(Type::new_proc_type(&arg_types, &result))},
+        LetLangExp::QualifiedVarExp(ref m, ref s) => {
+            let iface = match tenv.lookup_module(m) {
+                            Some(iface) => iface,
+                            None        => type_err!("Unknown module: {}", m),
+                            };
+            match iface.values.iter().find(|&&(ref v, _)| v == s) {
+                Some(&(_, ref t)) => Ok(t.clone()),
+                None              => type_err!("Module {} does not export {}", m, s),
+            }},
+        LetLangExp::NewObjectExp(ref c, ref rands) => {
+            let class = match tenv.lookup_class(c) {
+                            Some(ref class) if class.is_interface => type_err!("Cannot create an instance of interface {}", c),
+                            Some(class)                          => class,
+                            None                                 => type_err!("Unknown class: {}", c),
+                            };
+            if class.methods.contains_key("initialize") {
+                method_call_type(&class, &("initialize".to_string()), rands, tenv)?;
+            } else if !rands.is_empty() {
+                type_err!("class {} has no initialize method but new is given {} arguments", c, rands.len());
+            }
+            Ok(class.class_type())},
+        // an object of type any may have any method
+        LetLangExp::MethodCallExp(ref obj, ref m, ref rands) => match type_of(obj, tenv)? {
+            Type::ClassType(ref c, _) => match tenv.lookup_class(c) {
+                                             Some(class) => method_call_type(&class, m, rands, tenv),
+                                             None        => type_err!("Unknown class: {}", c),
+                                             },
+            Type::AnyType             => {
+                for e in rands.iter() {
+                    type_of(e, tenv)?;
+                }
+                Ok(Type::AnyType)},
+            t                         => type_err!("send {} {}: {} has type {}, which is not a class or interface",
+                                                   obj, m, obj, t),
+            },
+        LetLangExp::SuperCallExp(ref m, ref rands) => match tenv.apply_tenv(&("%super".to_string())) {
+            Some(Type::ClassType(ref c, _)) => match tenv.lookup_class(c) {
+                                                   Some(class) => method_call_type(&class, m, rands, tenv),
+                                                   None        => type_err!("Unknown class: {}", c),
+                                                   },
+            _                               => type_err!("super used outside of a method"),
+            },
+        LetLangExp::SelfExp => match tenv.apply_tenv(&("self".to_string())) {
+                                   Some(t) => Ok(t),
+                                   None    => type_err!("self used outside of a method"),
+                                   },
+        // the cast itself is checked when it runs
+        LetLangExp::CastExp(ref e, ref c) => {
+            expect_object(e, tenv, "cast")?;
+            match tenv.lookup_class(c) {
+                Some(class) => Ok(class.class_type()),
+                None        => type_err!("Unknown class: {}", c),
+            }},
+        LetLangExp::InstanceOfExp(ref e, ref c) => {
+            expect_object(e, tenv, "instanceof")?;
+            match tenv.lookup_class(c) {
+                Some(_) => Ok(Type::BoolType),
+                None    => type_err!("Unknown class: {}", c),
+            }},
+        // operations are untyped: an operand may be anything, and so may the
+        // value the handler resumes the perform with
+        LetLangExp::PerformExp(_, ref e) => {
+            expect_any(e, tenv, "operand of perform")?;
+            Ok(Type::AnyType)},
+        LetLangExp::HandleExp(ref body, ref h) => {
+            let body_type = type_of(body, tenv)?;
+            let mut result = match h.ret {
+                                 Some((ref x, ref e)) => type_of(e, &tenv.extend_tenv(x, &body_type))?,
+                                 None                 => body_type,
+                                 };
+            let resume_type = Type::new_proc_type(&vec![Type::AnyType], &Type::AnyType);
+            for c in h.clauses.iter() {
+                let clause_tenv = tenv.extend_tenv(&c.param, &Type::AnyType).extend_tenv(&c.resume, &resume_type);
+                let t = type_of(&c.body, &clause_tenv)?;
+                result = match Type::join(&result, &t) {
+                             Some(t) => t,
+                             None    => type_err!("Clause {} of handle has type {} but the handle has type {} in {}",
+                                                  c.op, t, result, exp),
+                             };
+            }
+            Ok(result)},
+        // the values a generator yields are not tracked
+        LetLangExp::GeneratorExp(ref e) => {
+            type_of(e, tenv)?;
+            Ok(Type::GeneratorType)},
+        LetLangExp::YieldExp(ref e) => expect_any(e, tenv, "operand of yield"),
+        LetLangExp::NextExp(ref e) => {
+            expect_type(e, &Type::GeneratorType, tenv, "operand of next")?;
+            Ok(Type::AnyType)},
+        LetLangExp::IsDoneExp(ref e) => {
+            expect_type(e, &Type::GeneratorType, tenv, "operand of done?")?;
+            Ok(Type::BoolType)},
+        // an amb with no choices never returns, so may have any type
+        LetLangExp::AmbExp(ref choices) => {
+            let mut result: Option<Type> = None;
+            for e in choices.iter() {
+                let t = type_of(e, tenv)?;
+                result = match result {
+                             None         => Some(t),
+                             Some(result) => match Type::join(&result, &t) {
+                                 Some(t) => Some(t),
+                                 None    => type_err!("Choices of amb have different types: {} and {} in {}", result, t, exp),
+                                 },
+                             };
+            }
+            Ok(result.unwrap_or(Type::AnyType))},
+        LetLangExp::RequireExp(ref e) => {
+            expect_type(e, &Type::BoolType, tenv, "test of require")?;
+            Ok(Type::BoolType)},
+        LetLangExp::CollectAllExp(ref e) => {
+            type_of(e, tenv)?;
+            Ok(Type::ListType)},
+        LetLangExp::FlipExp(ref e) => {
+            expect_type(e, &Type::IntType, tenv, "probability of flip")?;
+            Ok(Type::BoolType)},
+        LetLangExp::ObserveExp(ref e) => {
+            expect_type(e, &Type::BoolType, tenv, "test of observe")?;
+            Ok(Type::BoolType)},
+        // code is checked only when it runs, but what is spliced into it must be code
+        LetLangExp::QuoteExp(ref e) => {
+            check_splices(e, 1, tenv)?;
+            Ok(Type::CodeType)},
+        LetLangExp::SpliceExp(_) => type_err!("splice used outside of quote in {}", exp),
+        LetLangExp::RunExp(ref e) => {
+            expect_type(e, &Type::CodeType, tenv, "operand of run")?;
+            Ok(Type::AnyType)},
+    }}
+
+// the splices of a quote at the given nesting level; those at level 1 run
+// when the quote is evaluated, in the environment outside it
+fn check_splices(e: &Rc<LetLangExp>, level: u32, tenv: &TypeEnv) -> Result<Rc<LetLangExp>, TypeErr> {
+    match **e {
+        LetLangExp::SpliceExp(ref e1) if level == 1 => expect_type(e1, &Type::CodeType, tenv, "operand of splice")?,
+        LetLangExp::SpliceExp(ref e1) => { check_splices(e1, level - 1, tenv)?; },
+        LetLangExp::QuoteExp(ref e1)  => { check_splices(e1, level + 1, tenv)?; },
+        _                             => { e.map_children(|e1| check_splices(e1, level, tenv))?; },
+    }
+    Ok(e.clone())
+}
+
+// the result type of calling method m of class with rands
+fn method_call_type(class: &StaticClass, m: &String, rands: &Vec<Rc<LetLangExp>>, tenv: &TypeEnv) -> Result<Type, TypeErr> {
+    let sig = match class.methods.get(m) {
+                  Some(sig) => sig,
+                  None      => type_err!("{} {} has no method {}", class.kind(), class.name, m),
+                  };
+    match *sig {
+        Type::ProcType(ref arg_types, ref result) => {
+            if arg_types.len() != rands.len() {
+                type_err!("Method {} of {} {} expects {} arguments but is given {}; expected signature {}",
+                          m, class.kind(), class.name, arg_types.len(), rands.len(), sig);
+            }
+            for (i, (expected, e)) in arg_types.iter().zip(rands.iter()).enumerate() {
+                let actual = type_of(e, tenv)?;
+                if !Type::is_compatible(expected, &actual) {
+                    type_err!("Argument {} of method {} of {} {} should have type {} but {} has type {}; expected signature {}",
+                              i + 1, m, class.kind(), class.name, expected, e, actual, sig);
+                }
+            }
+            Ok((**result).clone())},
+        _ => Ok(Type::AnyType),
+    }}
+
+fn expect_object(e: &LetLangExp, tenv: &TypeEnv, what: &str) -> Result<(), TypeErr> {
+    match type_of(e, tenv)? {
+        Type::ClassType(..) | Type::AnyType => Ok(()),
+        t => type_err!("The operand of {} should be an object but {} has type {}", what, e, t),
+    }}
+
+// a value put where its type is forgotten, as dictionary values and the
+// operands of perform and yield are, comes back out as any; one of an opaque
+// type would then pass for its representation, so it may not go there
+fn expect_any(e: &LetLangExp, tenv: &TypeEnv, what: &str) -> Result<Type, TypeErr> {
+    let t = type_of(e, tenv)?;
+    if t.mentions_opaque() {
+        type_err!("The {} may not have an opaque type but {} has type {}", what, e, t);
+    }
+    Ok(t)
+}
+
+fn expect_type(e: &LetLangExp, expected: &Type, tenv: &TypeEnv, what: &str) -> Result<(), TypeErr> {
+    let actual = type_of(e, tenv)?;
+    if Type::is_compatible(expected, &actual) {
+        Ok(())
+    } else {
+        type_err!("The {} should have type {} but {} has type {}", what, expected, e, actual)
+    }}
+
+// a predicate in scope must accept a value of type t; a built-in one on
+// integers must be given an integer
+fn check_contract(c: &Contract, t: &Type, tenv: &TypeEnv, exp: &LetLangExp) -> Result<(), TypeErr> {
+    match *c {
+        Contract::Flat(ref pred) => match tenv.apply_tenv(pred) {
+            Some(pred_type) => {
+                let expected = Type::new_proc_type(&vec![t.clone()], &Type::BoolType);
+                if Type::is_compatible(&expected, &pred_type) {
+                    Ok(())
+                } else {
+                    type_err!("Contract {} has type {} but should have type {} in {}", pred, pred_type, expected, exp)
+                }},
+            None => match &pred[..] {
+                "positive?" | "negative?" | "zero?" | "even?" | "odd?" =>
+                    if Type::is_compatible(&Type::IntType, t) {
+                        Ok(())
+                    } else {
+                        type_err!("Contract {} applies to integers but is given type {} in {}", pred, t, exp)
+                    },
+                "int?" | "bool?" | "string?" | "proc?" | "any?" => Ok(()),
+                _ => type_err!("Unknown contract {} in {}", pred, exp),
+                },
+            },
+        Contract::Arrow(ref args, ref result) => match *t {
+            Type::ProcType(ref arg_types, ref result_type) if arg_types.len() == args.len() => {
+                for (a, t) in args.iter().zip(arg_types.iter()) {
+                    check_contract(a, t, tenv, exp)?;
+                }
+                check_contract(result, result_type, tenv, exp)},
+            Type::AnyType => {
+                for a in args.iter() {
+                    check_contract(a, &Type::AnyType, tenv, exp)?;
+                }
+                check_contract(result, &Type::AnyType, tenv, exp)},
+            _ => type_err!("Contract {} does not fit type {} in {}", c, t, exp),
+            },
+    }}
+
+// dictionary keys must be integers, booleans or strings
+fn expect_key(e: &LetLangExp, tenv: &TypeEnv) -> Result<(), TypeErr> {
+    match type_of(e, tenv)? {
+        Type::IntType | Type::BoolType | Type::StrType | Type::AnyType => Ok(()),
+        t => type_err!("Dictionary key {} has type {}; keys must be int, bool or string", e, t),
+    }}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use let_lang_scanner::*;
+    use let_lang_parser::*;
+
+    fn check(src: &str) -> Result<Type, TypeErr> {
+        let pgm = parse(&tokenize(src).unwrap()).unwrap();
+        type_of_program(&pgm, &TypeEnv::new_tenv())
+    }
+
+    #[test]
+    fn module_satisfying_its_interface() {
+        let t = check("module nums
+                         interface [a : int b : int pred : (int -> bool)]
+                         body [a = 33 x = -(a, 1) b = -(a, x) pred = proc (n : int) iszero(-(n, b))]
+                       let a = 10 in if from nums take pred(a) then -(from nums take a, a) else from nums take b");
+        assert_eq!(t.unwrap(), Type::IntType);
+    }
+
+    #[test]
+    fn module_body_must_match_interface() {
+        let wrong_type = check("module m interface [u : bool] body [u = 3] 1");
+        assert!(format!("{}", wrong_type.unwrap_err()).contains("u has type int in the body but the interface declares bool"));
+        let missing = check("module m interface [u : int v : int] body [u = 3] 1");
+        assert!(format!("{}", missing.unwrap_err()).contains("body does not define v"));
+        let hidden = check("module m interface [u : int] body [x = 1 u = x] from m take x");
+        assert!(format!("{}", hidden.unwrap_err()).contains("does not export x"));
+    }
+
+    #[test]
+    fn opaque_types_hide_their_representation() {
+        let counter = "module counter
+                         interface [opaque t  transparent step = int
+                                    zero : t  incr : (t -> t)  read : (t -> step)]
+                         body [type t = int  type step = int
+                               zero = 0  incr = proc (c : t) -(c, -1)  read = proc (c : t) c]";
+        let ok = check(&format!("{} let bump = proc (c : from counter take t) from counter take incr(c)
+                                    in from counter take read(bump(from counter take zero))", counter));
+        assert_eq!(ok.unwrap(), Type::IntType);
+        let leak = check(&format!("{} -(from counter take zero, 1)", counter));
+        assert!(format!("{}", leak.unwrap_err()).contains("has type from counter take t"));
+        let through_any = check(&format!("{} let f = proc (c) c in f(from counter take zero)", counter));
+        assert!(through_any.is_err());
+        let forged = check(&format!("{} from counter take incr(5)", counter));
+        assert!(forged.is_err());
+        for branches in &["dict-get({1: 5}, 1) else from counter take zero",
+                          "from counter take zero else dict-get({1: 5}, 1)"] {
+            let joined = check(&format!("{} from counter take read(from counter take incr(if true then {}))",
+                                        counter, branches));
+            assert!(format!("{}", joined.unwrap_err()).contains("Branches of if have different types"));
+        }
+    }
+
+    #[test]
+    fn opaque_values_cannot_pass_through_untyped_slots() {
+        let counter = "module counter interface [opaque t zero : t] body [type t = int zero = 0]";
+        let dict = check(&format!("{} -(dict-get({{1: from counter take zero}}, 1), 1)", counter));
+        assert!(format!("{}", dict.unwrap_err()).contains("The value of a dictionary may not have an opaque type"));
+        let set = check(&format!("{} dict-set({{}}, 1, from counter take zero)", counter));
+        assert!(format!("{}", set.unwrap_err()).contains("The value of dict-set may not have an opaque type"));
+        let perform = check(&format!("{} handle perform leak(from counter take zero) with {{leak(x, k) -> -(x, 1)}}",
+                                     counter));
+        assert!(format!("{}", perform.unwrap_err()).contains("The operand of perform may not have an opaque type"));
+        let yielded = check(&format!("{} generator yield from counter take zero", counter));
+        assert!(format!("{}", yielded.unwrap_err()).contains("The operand of yield may not have an opaque type"));
+    }
+
+    #[test]
+    fn amb_joins_its_choices() {
+        let counter = "module counter interface [opaque t zero : t] body [type t = int zero = 0]";
+        let opaque = check(&format!("{} amb(from counter take zero, from counter take zero)", counter));
+        assert!(opaque.is_ok());
+        let mixed = check(&format!("{} amb(dict-get({{1: 5}}, 1), from counter take zero)", counter));
+        assert!(format!("{}", mixed.unwrap_err()).contains("Choices of amb have different types"));
+        assert_eq!(check("amb()").unwrap(), Type::AnyType);
+    }
+
+    #[test]
+    fn module_types_must_match_interface() {
+        let missing = check("module m interface [opaque t  x : t] body [x = 1] 1");
+        assert!(format!("{}", missing.unwrap_err()).contains("body does not define type t"));
+        let wrong = check("module m interface [transparent t = int] body [type t = bool] 1");
+        assert!(format!("{}", wrong.unwrap_err()).contains("type t is bool in the body"));
+    }
+
+    const TREES: &'static str = "
+        interface tree
+          method int sum()
+          method bool equal(t : tree)
+        class interior extends object implements tree
+          field tree left
+          field tree right
+          method void initialize(l : tree, r : tree) begin set left = l; set right = r end
+          method tree getleft() left
+          method tree getright() right
+          method int sum() -(send left sum(), -(0, send right sum()))
+          method bool equal(t : tree)
+            if instanceof t interior
+            then if send left equal(send cast t interior getleft())
+                 then send right equal(send cast t interior getright())
+                 else false
+            else false
+        class leaf extends object implements tree
+          field int value
+          method void initialize(v : int) set value = v
+          method int sum() value
+          method int getvalue() value
+          method bool equal(t : tree)
+            if instanceof t leaf
+            then iszero(-(value, send cast t leaf getvalue()))
+            else false";
+
+    #[test]
+    fn classes_implementing_an_interface() {
+        let t = check(&format!("{} let t = new interior(new interior(new leaf(3), new leaf(4)),
+                                                              new leaf(5))
+                                   in send t equal(t)", TREES));
+        assert_eq!(t.unwrap(), Type::BoolType);
+        let sum = check(&format!("{} let t = new leaf(3) in send t sum()", TREES));
+        assert_eq!(sum.unwrap(), Type::IntType);
+    }
+
+    #[test]
+    fn sends_are_checked_against_method_signatures() {
+        let unknown = check(&format!("{} send new leaf(3) product()", TREES));
+        assert!(format!("{}", unknown.unwrap_err()).contains("class leaf has no method product"));
+        let bad_arg = check(&format!("{} send new leaf(3) equal(5)", TREES));
+        assert_eq!(format!("{}", bad_arg.unwrap_err()),
+                   "TypeError: Argument 1 of method equal of class leaf should have type tree \
+                    but 5 has type int; expected signature (tree -> bool) (line: 26, column: 24)");
+        let bad_new = check(&format!("{} new interior(new leaf(1), 2)", TREES));
+        assert!(format!("{}", bad_new.unwrap_err()).contains("Argument 2 of method initialize of class interior"));
+        let not_object = check("send 5 m()");
+        assert!(format!("{}", not_object.unwrap_err()).contains("is not a class or interface"));
+    }
+
+    #[test]
+    fn subtypes_and_casts() {
+        let upcast = check(&format!("{} let f = proc (t : tree) send t sum() in f(new leaf(2))", TREES));
+        assert_eq!(upcast.unwrap(), Type::IntType);
+        let downcast = check(&format!("{} let f = proc (t : tree) send t getvalue() in f(new leaf(2))", TREES));
+        assert!(format!("{}", downcast.unwrap_err()).contains("interface tree has no method getvalue"));
+        let narrowed = check(&format!("{} let apply = proc (f : (tree -> int)) f(new interior(new leaf(1), new leaf(2)))
+                                       in apply(proc (l : leaf) send l getvalue())", TREES));
+        assert!(format!("{}", narrowed.unwrap_err()).contains("Argument 1 of apply should have type (tree -> int)"));
+        let widened = check(&format!("{} let apply = proc (f : (leaf -> int)) f(new leaf(1))
+                                      in apply(proc (t : tree) send t sum())", TREES));
+        assert_eq!(widened.unwrap(), Type::IntType);
+        let cast = check(&format!("{} let f = proc (t : tree) send cast t leaf getvalue() in f(new leaf(2))", TREES));
+        assert_eq!(cast.unwrap(), Type::IntType);
+        let bad_cast = check(&format!("{} cast 3 leaf", TREES));
+        assert!(bad_cast.is_err());
+    }
+
+    #[test]
+    fn classes_must_implement_their_interfaces() {
+        let missing = check("interface shape method int area()
+                             class square extends object implements shape field int side method int perimeter() side
+                             1");
+        assert_eq!(format!("{}", missing.unwrap_err()),
+                   "TypeError: class square does not implement method area of interface shape, \
+                    expected signature (-> int)");
+        let wrong = check("interface shape method int area()
+                           class square extends object implements shape method bool area() true
+                           1");
+        assert!(format!("{}", wrong.unwrap_err()).contains("method area has signature (-> bool) but interface shape expects (-> int)"));
+        let bad_override = check("class base extends object method int m(x : int) x
+                                  class derived extends base method int m(x : bool) 1
+                                  1");
+        assert!(format!("{}", bad_override.unwrap_err()).contains("overrides method m of class base with signature (int -> int)"));
+        let narrower_arg = check(&format!("{} class base extends object method int m(x : tree) send x sum()
+                                             class derived extends base method int m(x : leaf) send x getvalue()
+                                             let f = proc (b : base) send b m(new interior(new leaf(1), new leaf(2)))
+                                             in f(new derived())", TREES));
+        assert!(format!("{}", narrower_arg.unwrap_err()).contains("overrides method m of class base with signature (tree -> int)"));
+        let bad_body = check("class base extends object method int m() true 1");
+        assert!(format!("{}", bad_body.unwrap_err()).contains("method m should return int but its body has type bool"));
+    }
+
+    #[test]
+    fn splices_must_be_code() {
+        assert_eq!(check("let c = quote 1 in quote -(splice c, 2)").unwrap(), Type::CodeType);
+        assert_eq!(check("run quote quote splice splice quote 3").unwrap(), Type::AnyType);
+        assert!(format!("{}", check("quote -(splice 1, 2)").unwrap_err()).contains("operand of splice"));
+        assert!(format!("{}", check("splice quote 1").unwrap_err()).contains("splice used outside of quote"));
+        assert!(format!("{}", check("run 1").unwrap_err()).contains("operand of run"));
+    }
+
+    #[test]
+    fn contracts_must_fit_the_bound_type() {
+        assert_eq!(check("let x : positive? = 3 in assert(iszero(x), \"zero\")").unwrap(), Type::BoolType);
+        assert_eq!(check("let f : (positive? -> even?) = proc (n : int) n in f(2)").unwrap(), Type::IntType);
+        assert!(format!("{}", check("let x : positive? = true in x").unwrap_err())
+                .contains("Contract positive? applies to integers but is given type bool"));
+        assert!(format!("{}", check("let x : odd = 1 in x").unwrap_err()).contains("Unknown contract odd"));
+        assert!(format!("{}", check("let f : (int? -> int?) = 3 in f").unwrap_err())
+                .contains("Contract (int? -> int?) does not fit type int"));
+        assert!(format!("{}", check("assert(1, \"one\")").unwrap_err()).contains("test of assert"));
+    }
+
+    #[test]
+    fn type_errors_point_at_the_innermost_expression() {
+        let err = check("let x = 1\nin if iszero(x) then -(x, true) else x").unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "TypeError: The second operand of - should have type int but true has type bool (line: 2, column: 22)");
+    }
+}
@@ -0,0 +1,117 @@
+// Classes and objects for the CLASSES language
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+use let_lang_exp::*;
+use let_lang_env::*;
+use int_bool::*;
+
+// field_names lists inherited fields first; a field shadowed by a subclass
+// is renamed x%1, x%2, ... so that lookups by name find the newest one.
+// methods holds inherited methods too, so dispatch is one hash lookup;
+// ancestors names the class, its superclasses and every interface they
+// implement, for instanceof and cast
+pub struct Class {
+    pub name: String,
+    pub super_name: Option<String>,
+    pub ancestors: Vec<String>,
+    pub field_names: Vec<String>,
+    pub methods: HashMap<String, Rc<Method>>,
+}
+
+// a method sees the fields of its host class, self, and the environment the
+// class was declared in
+pub struct Method {
+    pub params: Vec<String>,
+    pub body: Rc<LetLangExp>,
+    pub super_name: String,
+    pub field_names: Vec<String>,
+    pub env: LetLangEnv,
+}
+
+// an object's fields are assignable cells, shared with the methods running on it
+pub struct Object {
+    pub class: Rc<Class>,
+    pub fields: Vec<Rc<RefCell<IntBool>>>,
+}
+
+impl Class {
+    // the root of every class hierarchy
+    pub fn new_object_class() -> Self {
+        Class { name: "object".to_string(), super_name: None, ancestors: vec!["object".to_string()],
+                field_names: Vec::new(), methods: HashMap::new() }
+    }
+    pub fn new_class(decl: &ClassDecl, super_class: &Class, env: &LetLangEnv) -> Self {
+        let own_fields: Vec<String> = decl.fields.iter().map(|&(ref f, _)| f.clone()).collect();
+        let field_names = append_field_names(&super_class.field_names, &own_fields);
+        let mut methods = super_class.methods.clone();
+        for m in decl.methods.iter() {
+            let params = m.sig.params.iter().map(|&(ref x, _)| x.clone()).collect();
+            methods.insert(m.sig.name.clone(), Rc::new(Method { params: params,
+                                                                body: m.body.clone(),
+                                                                super_name: decl.super_name.clone(),
+                                                                field_names: field_names.clone(),
+                                                                env: env.clone() }));
+        }
+        let mut ancestors = vec![decl.name.clone()];
+        ancestors.extend(super_class.ancestors.iter().cloned());
+        ancestors.extend(decl.interfaces.iter().cloned());
+        Class { name: decl.name.clone(), super_name: Some(decl.super_name.clone()), ancestors: ancestors,
+                field_names: field_names, methods: methods }
+    }
+    // is every instance of this class also an instance of c?
+    pub fn is_subclass_of(&self, c: &String) -> bool {
+        self.ancestors.contains(c)
+    }
+    pub fn find_method(&self, m: &String) -> Option<Rc<Method>> {
+        self.methods.get(m).cloned()
+    }
+}
+
+impl Object {
+    // fields start out as 0 until initialize assigns them
+    pub fn new_object(class: &Rc<Class>) -> Self {
+        Object { class: class.clone(),
+                 fields: class.field_names.iter().map(|_| Rc::new(RefCell::new(IntBool::Integer(0)))).collect() }
+    }
+}
+
+pub fn append_field_names(super_fields: &Vec<String>, new_fields: &Vec<String>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for f in super_fields.iter() {
+        if new_fields.contains(f) {
+            names.push(fresh_field_name(f, super_fields));
+        } else {
+            names.push(f.clone());
+        }
+    }
+    names.extend(new_fields.iter().cloned());
+    names
+}
+
+fn fresh_field_name(f: &String, taken: &Vec<String>) -> String {
+    let mut i = 1;
+    loop {
+        let name = format!("{}%{}", f, i);
+        if !taken.contains(&name) {
+            return name;
+        }
+        i += 1;
+    }}
+
+// objects are equal only to themselves
+impl PartialEq for Object {
+    fn eq(&self, other: &Object) -> bool {
+        self as *const Object == other as *const Object
+    }}
+impl fmt::Debug for Object { // fields may refer back to the object
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<object {}>", self.class.name)
+    }}
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }}
@@ -1,11 +1,18 @@
 use std::rc::Rc;
+use std::cell::RefCell;
 use std::fmt;
 use int_bool::*;
+use let_lang_exp::*;
 
+// every variable is bound to an assignable cell (implicit references);
+// ExtendEnvRec binds a recursive procedure, such as the loop of a named let;
+// ExtendEnvModule binds a module name to the environment of its exported names
 #[derive(Debug,Clone)]
 pub enum LetLangEnv {
     EmptyEnv,
-    ExtendEnv(String, IntBool, Rc<LetLangEnv>),
+    ExtendEnv(String, Rc<RefCell<IntBool>>, Rc<LetLangEnv>),
+    ExtendEnvRec(String, Vec<String>, Rc<LetLangExp>, Rc<LetLangEnv>),
+    ExtendEnvModule(String, Rc<LetLangEnv>, Rc<LetLangEnv>),
 }
 
 impl LetLangEnv {
@@ -13,18 +20,67 @@ impl LetLangEnv {
         LetLangEnv::EmptyEnv
     }
     pub fn extend_env(&self, s:&String, val: IntBool) -> Self {
-        LetLangEnv::ExtendEnv(s.clone(), val, Rc::new(self.clone()))
+        LetLangEnv::ExtendEnv(s.clone(), Rc::new(RefCell::new(val)), Rc::new(self.clone()))
+    }
+    // binds s to an existing cell, such as an object's field
+    pub fn extend_env_ref(&self, s:&String, cell: &Rc<RefCell<IntBool>>) -> Self {
+        LetLangEnv::ExtendEnv(s.clone(), cell.clone(), Rc::new(self.clone()))
+    }
+    pub fn extend_env_rec(&self, s:&String, params: &Vec<String>, body: &Rc<LetLangExp>) -> Self {
+        LetLangEnv::ExtendEnvRec(s.clone(), params.clone(), body.clone(), Rc::new(self.clone()))
+    }
+    pub fn extend_env_module(&self, m:&String, exports: &LetLangEnv) -> Self {
+        LetLangEnv::ExtendEnvModule(m.clone(), Rc::new(exports.clone()), Rc::new(self.clone()))
     }
     pub fn apply_env(&self, s:&String) -> Option<IntBool> {
-        match self.clone() {
-            LetLangEnv::ExtendEnv(var, val, env) =>
+        match *self {
+            LetLangEnv::ExtendEnv(ref var, ref val, ref env) =>
                                        if s[..] == var[..] {
-                                        Some(val)
+                                        Some(val.borrow().clone())
+                                       } else {
+                                        env.apply_env(s)
+                                        },
+            LetLangEnv::ExtendEnvRec(ref name, ref params, ref body, ref env) =>
+                                       if s[..] == name[..] { // the closure's env contains itself
+                                        Some(IntBool::Proc(Rc::new(Proc::new_proc(Some(name), params, body, self))))
                                        } else {
                                         env.apply_env(s)
                                         },
+            LetLangEnv::ExtendEnvModule(_, _, ref env) => env.apply_env(s),
             LetLangEnv::EmptyEnv => None,
         }}
+    // from m take s
+    pub fn apply_env_qualified(&self, m:&String, s:&String) -> Option<IntBool> {
+        match *self {
+            LetLangEnv::ExtendEnvModule(ref name, ref exports, ref env) =>
+                                       if m[..] == name[..] {
+                                        exports.apply_env(s)
+                                       } else {
+                                        env.apply_env_qualified(m, s)
+                                        },
+            LetLangEnv::ExtendEnv(_, _, ref env)       => env.apply_env_qualified(m, s),
+            LetLangEnv::ExtendEnvRec(_, _, _, ref env) => env.apply_env_qualified(m, s),
+            LetLangEnv::EmptyEnv => None,
+        }}
+    // assigns to the nearest binding of s; false if s is unbound or not assignable
+    pub fn set_env(&self, s:&String, new_val: IntBool) -> bool {
+        match *self {
+            LetLangEnv::ExtendEnv(ref var, ref val, ref env) =>
+                                       if s[..] == var[..] {
+                                        *val.borrow_mut() = new_val;
+                                        true
+                                       } else {
+                                        env.set_env(s, new_val)
+                                        },
+            LetLangEnv::ExtendEnvRec(ref name, _, _, ref env) =>
+                                       if s[..] == name[..] {
+                                        false
+                                       } else {
+                                        env.set_env(s, new_val)
+                                        },
+            LetLangEnv::ExtendEnvModule(_, _, ref env) => env.set_env(s, new_val),
+            LetLangEnv::EmptyEnv => false,
+        }}
     pub fn is_null_env(&self) -> bool {
         match self.clone() {
             LetLangEnv::EmptyEnv  => true,
@@ -36,7 +92,21 @@ impl LetLangEnv {
             LetLangEnv::ExtendEnv(var,val,env) => {let mut temp = "[".to_string();
                                                 temp.push_str(&(var.to_string()));
                                                 temp.push_str(&(", ".to_string()));
-                                                temp.push_str(&(val.to_string()));
+                                                temp.push_str(&(val.borrow().to_string()));
+                                                temp.push_str(&(" ".to_string()));
+                                                temp.push_str(&(env.to_string()));
+                                                temp.push_str(&("]".to_string()));
+                                                temp},
+            LetLangEnv::ExtendEnvRec(name,_,_,env) => {let mut temp = "[".to_string();
+                                                temp.push_str(&(name.to_string()));
+                                                temp.push_str(&(", <procedure> ".to_string()));
+                                                temp.push_str(&(env.to_string()));
+                                                temp.push_str(&("]".to_string()));
+                                                temp},
+            LetLangEnv::ExtendEnvModule(m,exports,env) => {let mut temp = "[module ".to_string();
+                                                temp.push_str(&(m.to_string()));
+                                                temp.push_str(&(" ".to_string()));
+                                                temp.push_str(&(exports.to_string()));
                                                 temp.push_str(&(" ".to_string()));
                                                 temp.push_str(&(env.to_string()));
                                                 temp.push_str(&("]".to_string()));
@@ -55,14 +125,24 @@ impl fmt::Display for LetLangEnv {
 #[cfg(test)]
 mod test {
     use super::LetLangEnv;
-    use let_lang_exp::*;
+    use int_bool::*;
 
     #[test]
     fn basic_tests() {
         let null_env = LetLangEnv::new_env();
         assert!(null_env.is_null_env());
 
-        let env2 = null_env.extend_env(&("var1".to_string()), 25);
+        let env2 = null_env.extend_env(&("var1".to_string()), IntBool::Integer(25));
         assert!(!(env2.is_null_env()));
     }
+
+    #[test]
+    fn set_env_updates_shared_cell() {
+        let x = "x".to_string();
+        let env1 = LetLangEnv::new_env().extend_env(&x, IntBool::Integer(1));
+        let env2 = env1.extend_env(&("y".to_string()), IntBool::Boolean(true));
+        assert!(env2.set_env(&x, IntBool::Integer(2)));
+        assert_eq!(env1.apply_env(&x), Some(IntBool::Integer(2)));
+        assert!(!env2.set_env(&("z".to_string()), IntBool::Integer(3)));
+    }
 }
@@ -2,9 +2,12 @@
 
 use std::rc::Rc; // Rc<T> reference counted pointer type over immutable value
 use std::fmt;
+use int_bool::quote_str;
+use let_lang_type::*;
+use let_lang_scanner::Span;
 
 // data type for abstract-syntax tree
-#[derive(Debug,Clone)]
+#[derive(Debug,Clone,PartialEq)]
 pub enum LetLangExp {  // set of possible LetLangExp's
     ConstExp(i32),
     Boolean(bool),
@@ -13,6 +16,71 @@ pub enum LetLangExp {  // set of possible LetLangExp's
     IfExp(Rc<LetLangExp>, Rc<LetLangExp>, Rc<LetLangExp>),
     VarExp(String),
     LetExp(String, Rc<LetLangExp>, Rc<LetLangExp>),
+    StrExp(String),
+    DictExp(Vec<(Rc<LetLangExp>, Rc<LetLangExp>)>),        // {k1: v1, ...}
+    DictGetExp(Rc<LetLangExp>, Rc<LetLangExp>),            // dict-get(d, k)
+    DictSetExp(Rc<LetLangExp>, Rc<LetLangExp>, Rc<LetLangExp>),
+    DictHasExp(Rc<LetLangExp>, Rc<LetLangExp>),
+    DictRemoveExp(Rc<LetLangExp>, Rc<LetLangExp>),
+    DictKeysExp(Rc<LetLangExp>),
+    BeginExp(Vec<Rc<LetLangExp>>),                         // begin e1; e2; ... end
+    NamedLetExp(String, Vec<(String, Rc<LetLangExp>)>, Rc<LetLangExp>), // let loop (i = 0) in ...
+    CallExp(Rc<LetLangExp>, Vec<Rc<LetLangExp>>),          // f(e1, ..., en)
+    WhileExp(Rc<LetLangExp>, Rc<LetLangExp>),              // while test do body
+    SetExp(String, Rc<LetLangExp>),                        // set x = e
+    ProcExp(Vec<(String, Option<Type>)>, Rc<LetLangExp>),  // proc (x : int, y) body
+    QualifiedVarExp(String, String),                       // from m take x
+    NewObjectExp(String, Vec<Rc<LetLangExp>>),             // new c(e1, ..., en)
+    MethodCallExp(Rc<LetLangExp>, String, Vec<Rc<LetLangExp>>), // send obj m(e1, ..., en)
+    SuperCallExp(String, Vec<Rc<LetLangExp>>),             // super m(e1, ..., en)
+    SelfExp,
+    CastExp(Rc<LetLangExp>, String),                       // cast e c
+    InstanceOfExp(Rc<LetLangExp>, String),                 // instanceof e c
+    PerformExp(String, Rc<LetLangExp>),                    // perform op(e)
+    HandleExp(Rc<LetLangExp>, Rc<Handler>),                // handle e with {clause, ...}
+    GeneratorExp(Rc<LetLangExp>),                          // generator e
+    YieldExp(Rc<LetLangExp>),                              // yield e
+    NextExp(Rc<LetLangExp>),                               // next(g)
+    IsDoneExp(Rc<LetLangExp>),                             // done?(g)
+    AmbExp(Vec<Rc<LetLangExp>>),                           // amb(e1, ..., en)
+    RequireExp(Rc<LetLangExp>),                            // require(test)
+    CollectAllExp(Rc<LetLangExp>),                         // collect-all e
+    FlipExp(Rc<LetLangExp>),                               // flip(p), p a percent from 0 to 100
+    ObserveExp(Rc<LetLangExp>),                            // observe(test)
+    QuoteExp(Rc<LetLangExp>),                              // quote e
+    SpliceExp(Rc<LetLangExp>),                             // splice e, inside a quote
+    RunExp(Rc<LetLangExp>),                                // run e
+    AssertExp(Rc<LetLangExp>, String),                     // assert(test, "message")
+    ContractLetExp(String, Rc<Contract>, Rc<LetLangExp>, Rc<LetLangExp>), // let x : c = e in body
+    SpannedExp(Span, Rc<LetLangExp>),                      // e and the source text it was parsed from
+    ErrorExp,                                              // where the parser skipped a syntax error
+}
+
+// checked at runtime against the value bound by a let: a predicate, either
+// built in, such as positive?, or a one-argument procedure in scope; or
+// (c1, ..., cn -> c), which checks each argument and the result of every
+// call of the procedure bound
+#[derive(Debug,Clone,PartialEq)]
+pub enum Contract {
+    Flat(String),
+    Arrow(Vec<Rc<Contract>>, Rc<Contract>),
+}
+
+// op(x, k) -> e runs when the handled expression performs op, with x bound to
+// the operand and k to the continuation of the perform, up to and including
+// the handle; return(x) -> e, if present, transforms the handled value
+#[derive(Debug,Clone,PartialEq)]
+pub struct Handler {
+    pub clauses: Vec<OpClause>,
+    pub ret: Option<(String, Rc<LetLangExp>)>,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct OpClause {
+    pub op: String,
+    pub param: String,
+    pub resume: String,
+    pub body: Rc<LetLangExp>,
 }
 
 // create a constructor and to_string() method for each type of LetLangExp
@@ -38,6 +106,188 @@ impl LetLangExp {
     pub fn new_let_exp(s: &String, arg1: &LetLangExp, arg2: &LetLangExp) -> Self {
         LetLangExp::LetExp(s.clone(), Rc::new(arg1.clone()), Rc::new(arg2.clone()))
     }
+    pub fn new_str_exp(s: &String) -> Self {
+        LetLangExp::StrExp(s.clone())
+    }
+    pub fn new_dict_exp(entries: &Vec<(LetLangExp, LetLangExp)>) -> Self {
+        LetLangExp::DictExp(entries.iter()
+                                   .map(|&(ref k, ref v)| (Rc::new(k.clone()), Rc::new(v.clone())))
+                                   .collect())
+    }
+    pub fn new_dict_get(dict: &LetLangExp, key: &LetLangExp) -> Self {
+        LetLangExp::DictGetExp(Rc::new(dict.clone()), Rc::new(key.clone()))
+    }
+    pub fn new_dict_set(dict: &LetLangExp, key: &LetLangExp, val: &LetLangExp) -> Self {
+        LetLangExp::DictSetExp(Rc::new(dict.clone()), Rc::new(key.clone()), Rc::new(val.clone()))
+    }
+    pub fn new_dict_has(dict: &LetLangExp, key: &LetLangExp) -> Self {
+        LetLangExp::DictHasExp(Rc::new(dict.clone()), Rc::new(key.clone()))
+    }
+    pub fn new_dict_remove(dict: &LetLangExp, key: &LetLangExp) -> Self {
+        LetLangExp::DictRemoveExp(Rc::new(dict.clone()), Rc::new(key.clone()))
+    }
+    pub fn new_dict_keys(dict: &LetLangExp) -> Self {
+        LetLangExp::DictKeysExp(Rc::new(dict.clone()))
+    }
+    pub fn new_begin_exp(exps: &Vec<LetLangExp>) -> Self {
+        LetLangExp::BeginExp(exps.iter().map(|e| Rc::new(e.clone())).collect())
+    }
+    pub fn new_named_let_exp(s: &String, bindings: &Vec<(String, LetLangExp)>, body: &LetLangExp) -> Self {
+        LetLangExp::NamedLetExp(s.clone(),
+                                bindings.iter().map(|&(ref v, ref e)| (v.clone(), Rc::new(e.clone()))).collect(),
+                                Rc::new(body.clone()))
+    }
+    pub fn new_call_exp(rator: &LetLangExp, rands: &Vec<LetLangExp>) -> Self {
+        LetLangExp::CallExp(Rc::new(rator.clone()), rands.iter().map(|e| Rc::new(e.clone())).collect())
+    }
+    pub fn new_while_exp(test: &LetLangExp, body: &LetLangExp) -> Self {
+        LetLangExp::WhileExp(Rc::new(test.clone()), Rc::new(body.clone()))
+    }
+    pub fn new_set_exp(s: &String, arg: &LetLangExp) -> Self {
+        LetLangExp::SetExp(s.clone(), Rc::new(arg.clone()))
+    }
+    pub fn new_proc_exp(params: &Vec<(String, Option<Type>)>, body: &LetLangExp) -> Self {
+        LetLangExp::ProcExp(params.clone(), Rc::new(body.clone()))
+    }
+    pub fn new_qualified_var_exp(m: &String, s: &String) -> Self {
+        LetLangExp::QualifiedVarExp(m.clone(), s.clone())
+    }
+    pub fn new_object_exp(c: &String, rands: &Vec<LetLangExp>) -> Self {
+        LetLangExp::NewObjectExp(c.clone(), rands.iter().map(|e| Rc::new(e.clone())).collect())
+    }
+    pub fn new_method_call_exp(obj: &LetLangExp, m: &String, rands: &Vec<LetLangExp>) -> Self {
+        LetLangExp::MethodCallExp(Rc::new(obj.clone()), m.clone(), rands.iter().map(|e| Rc::new(e.clone())).collect())
+    }
+    pub fn new_cast_exp(arg: &LetLangExp, c: &String) -> Self {
+        LetLangExp::CastExp(Rc::new(arg.clone()), c.clone())
+    }
+    pub fn new_instance_of_exp(arg: &LetLangExp, c: &String) -> Self {
+        LetLangExp::InstanceOfExp(Rc::new(arg.clone()), c.clone())
+    }
+    pub fn new_perform_exp(op: &String, arg: &LetLangExp) -> Self {
+        LetLangExp::PerformExp(op.clone(), Rc::new(arg.clone()))
+    }
+    pub fn new_handle_exp(body: &LetLangExp, handler: &Handler) -> Self {
+        LetLangExp::HandleExp(Rc::new(body.clone()), Rc::new(handler.clone()))
+    }
+    pub fn new_generator_exp(body: &LetLangExp) -> Self {
+        LetLangExp::GeneratorExp(Rc::new(body.clone()))
+    }
+    pub fn new_yield_exp(arg: &LetLangExp) -> Self {
+        LetLangExp::YieldExp(Rc::new(arg.clone()))
+    }
+    pub fn new_next_exp(arg: &LetLangExp) -> Self {
+        LetLangExp::NextExp(Rc::new(arg.clone()))
+    }
+    pub fn new_is_done_exp(arg: &LetLangExp) -> Self {
+        LetLangExp::IsDoneExp(Rc::new(arg.clone()))
+    }
+    pub fn new_amb_exp(choices: &Vec<LetLangExp>) -> Self {
+        LetLangExp::AmbExp(choices.iter().map(|e| Rc::new(e.clone())).collect())
+    }
+    pub fn new_require_exp(test: &LetLangExp) -> Self {
+        LetLangExp::RequireExp(Rc::new(test.clone()))
+    }
+    pub fn new_collect_all_exp(e: &LetLangExp) -> Self {
+        LetLangExp::CollectAllExp(Rc::new(e.clone()))
+    }
+    pub fn new_flip_exp(p: &LetLangExp) -> Self {
+        LetLangExp::FlipExp(Rc::new(p.clone()))
+    }
+    pub fn new_observe_exp(test: &LetLangExp) -> Self {
+        LetLangExp::ObserveExp(Rc::new(test.clone()))
+    }
+    pub fn new_super_call_exp(m: &String, rands: &Vec<LetLangExp>) -> Self {
+        LetLangExp::SuperCallExp(m.clone(), rands.iter().map(|e| Rc::new(e.clone())).collect())
+    }
+    pub fn new_quote_exp(e: &LetLangExp) -> Self {
+        LetLangExp::QuoteExp(Rc::new(e.clone()))
+    }
+    pub fn new_splice_exp(e: &LetLangExp) -> Self {
+        LetLangExp::SpliceExp(Rc::new(e.clone()))
+    }
+    pub fn new_run_exp(e: &LetLangExp) -> Self {
+        LetLangExp::RunExp(Rc::new(e.clone()))
+    }
+    pub fn new_spanned_exp(span: &Span, e: &LetLangExp) -> Self {
+        LetLangExp::SpannedExp(*span, Rc::new(e.clone()))
+    }
+    pub fn new_assert_exp(test: &LetLangExp, message: &String) -> Self {
+        LetLangExp::AssertExp(Rc::new(test.clone()), message.clone())
+    }
+    pub fn new_contract_let_exp(s: &String, c: &Contract, e1: &LetLangExp, e2: &LetLangExp) -> Self {
+        LetLangExp::ContractLetExp(s.clone(), Rc::new(c.clone()), Rc::new(e1.clone()), Rc::new(e2.clone()))
+    }
+    // rebuilds this expression with f applied to each immediate subexpression,
+    // left to right; names, including those of binders, are kept as they are
+    pub fn map_children<E, F>(&self, mut f: F) -> Result<LetLangExp, E>
+        where F: FnMut(&Rc<LetLangExp>) -> Result<Rc<LetLangExp>, E> {
+        Ok(match *self {
+            LetLangExp::ConstExp(_) | LetLangExp::Boolean(_) | LetLangExp::VarExp(_) | LetLangExp::StrExp(_)
+            | LetLangExp::QualifiedVarExp(..) | LetLangExp::SelfExp | LetLangExp::ErrorExp => self.clone(),
+            LetLangExp::DiffExp(ref e1, ref e2)     => LetLangExp::DiffExp(f(e1)?, f(e2)?),
+            LetLangExp::IsZeroExp(ref e)            => LetLangExp::IsZeroExp(f(e)?),
+            LetLangExp::IfExp(ref e1, ref e2, ref e3) => LetLangExp::IfExp(f(e1)?, f(e2)?, f(e3)?),
+            LetLangExp::LetExp(ref s, ref e1, ref e2) => LetLangExp::LetExp(s.clone(), f(e1)?, f(e2)?),
+            LetLangExp::DictExp(ref entries)        => {
+                let mut new_entries = Vec::new();
+                for &(ref k, ref v) in entries.iter() {
+                    new_entries.push((f(k)?, f(v)?));
+                }
+                LetLangExp::DictExp(new_entries)},
+            LetLangExp::DictGetExp(ref d, ref k)    => LetLangExp::DictGetExp(f(d)?, f(k)?),
+            LetLangExp::DictSetExp(ref d, ref k, ref v) => LetLangExp::DictSetExp(f(d)?, f(k)?, f(v)?),
+            LetLangExp::DictHasExp(ref d, ref k)    => LetLangExp::DictHasExp(f(d)?, f(k)?),
+            LetLangExp::DictRemoveExp(ref d, ref k) => LetLangExp::DictRemoveExp(f(d)?, f(k)?),
+            LetLangExp::DictKeysExp(ref d)          => LetLangExp::DictKeysExp(f(d)?),
+            LetLangExp::BeginExp(ref exps)          => LetLangExp::BeginExp(map_all(exps, &mut f)?),
+            LetLangExp::NamedLetExp(ref name, ref bindings, ref body) => {
+                let mut new_bindings = Vec::new();
+                for &(ref v, ref e) in bindings.iter() {
+                    new_bindings.push((v.clone(), f(e)?));
+                }
+                LetLangExp::NamedLetExp(name.clone(), new_bindings, f(body)?)},
+            LetLangExp::CallExp(ref rator, ref rands) => LetLangExp::CallExp(f(rator)?, map_all(rands, &mut f)?),
+            LetLangExp::WhileExp(ref test, ref body) => LetLangExp::WhileExp(f(test)?, f(body)?),
+            LetLangExp::SetExp(ref s, ref e)        => LetLangExp::SetExp(s.clone(), f(e)?),
+            LetLangExp::ProcExp(ref params, ref body) => LetLangExp::ProcExp(params.clone(), f(body)?),
+            LetLangExp::NewObjectExp(ref c, ref rands) => LetLangExp::NewObjectExp(c.clone(), map_all(rands, &mut f)?),
+            LetLangExp::MethodCallExp(ref obj, ref m, ref rands) =>
+                LetLangExp::MethodCallExp(f(obj)?, m.clone(), map_all(rands, &mut f)?),
+            LetLangExp::SuperCallExp(ref m, ref rands) => LetLangExp::SuperCallExp(m.clone(), map_all(rands, &mut f)?),
+            LetLangExp::CastExp(ref e, ref c)       => LetLangExp::CastExp(f(e)?, c.clone()),
+            LetLangExp::InstanceOfExp(ref e, ref c) => LetLangExp::InstanceOfExp(f(e)?, c.clone()),
+            LetLangExp::PerformExp(ref op, ref e)   => LetLangExp::PerformExp(op.clone(), f(e)?),
+            LetLangExp::HandleExp(ref e, ref h)     => {
+                let body = f(e)?;
+                let mut clauses = Vec::new();
+                for c in h.clauses.iter() {
+                    clauses.push(OpClause { op: c.op.clone(), param: c.param.clone(), resume: c.resume.clone(),
+                                            body: f(&c.body)? });
+                }
+                let ret = match h.ret {
+                              Some((ref x, ref e)) => Some((x.clone(), f(e)?)),
+                              None                 => None,
+                              };
+                LetLangExp::HandleExp(body, Rc::new(Handler { clauses: clauses, ret: ret }))},
+            LetLangExp::GeneratorExp(ref e)         => LetLangExp::GeneratorExp(f(e)?),
+            LetLangExp::YieldExp(ref e)             => LetLangExp::YieldExp(f(e)?),
+            LetLangExp::NextExp(ref e)              => LetLangExp::NextExp(f(e)?),
+            LetLangExp::IsDoneExp(ref e)            => LetLangExp::IsDoneExp(f(e)?),
+            LetLangExp::AmbExp(ref choices)         => LetLangExp::AmbExp(map_all(choices, &mut f)?),
+            LetLangExp::RequireExp(ref e)           => LetLangExp::RequireExp(f(e)?),
+            LetLangExp::CollectAllExp(ref e)        => LetLangExp::CollectAllExp(f(e)?),
+            LetLangExp::FlipExp(ref e)              => LetLangExp::FlipExp(f(e)?),
+            LetLangExp::ObserveExp(ref e)           => LetLangExp::ObserveExp(f(e)?),
+            LetLangExp::QuoteExp(ref e)             => LetLangExp::QuoteExp(f(e)?),
+            LetLangExp::SpliceExp(ref e)            => LetLangExp::SpliceExp(f(e)?),
+            LetLangExp::RunExp(ref e)               => LetLangExp::RunExp(f(e)?),
+            LetLangExp::AssertExp(ref e, ref msg)   => LetLangExp::AssertExp(f(e)?, msg.clone()),
+            LetLangExp::SpannedExp(span, ref e)     => LetLangExp::SpannedExp(span, f(e)?),
+            LetLangExp::ContractLetExp(ref s, ref c, ref e1, ref e2) =>
+                LetLangExp::ContractLetExp(s.clone(), c.clone(), f(e1)?, f(e2)?),
+        })
+    }
     // a string representation, to be used by the formatter, for each type of LetLangExp
     pub fn to_string(&self) -> String {
         match self.clone() {
@@ -68,9 +318,302 @@ impl LetLangExp {
                                                 temp.push_str(&(" in ".to_string()));
                                                 temp.push_str(&(e2.to_string()));
                                                 temp}
+            LetLangExp::StrExp(s)           => quote_str(&s),
+            LetLangExp::DictExp(entries)    => {let mut temp = "{".to_string();
+                                                for (i, &(ref k, ref v)) in entries.iter().enumerate() {
+                                                    if i > 0 { temp.push_str(", ") };
+                                                    temp.push_str(&(k.to_string()));
+                                                    temp.push_str(": ");
+                                                    temp.push_str(&(v.to_string()));
+                                                }
+                                                temp.push_str("}");
+                                                temp}
+            LetLangExp::DictGetExp(d, k)    => prim_to_string("dict-get", &[d, k]),
+            LetLangExp::DictSetExp(d, k, v) => prim_to_string("dict-set", &[d, k, v]),
+            LetLangExp::DictHasExp(d, k)    => prim_to_string("dict-has?", &[d, k]),
+            LetLangExp::DictRemoveExp(d, k) => prim_to_string("dict-remove", &[d, k]),
+            LetLangExp::DictKeysExp(d)      => prim_to_string("dict-keys", &[d]),
+            LetLangExp::BeginExp(exps)      => {let mut temp = "begin ".to_string();
+                                                for (i, e) in exps.iter().enumerate() {
+                                                    if i > 0 { temp.push_str("; ") };
+                                                    temp.push_str(&(e.to_string()));
+                                                }
+                                                temp.push_str(" end");
+                                                temp}
+            LetLangExp::NamedLetExp(name, bindings, body) =>
+                                               {let mut temp = "let ".to_string();
+                                                temp.push_str(&name);
+                                                temp.push_str(" (");
+                                                for (i, &(ref v, ref e)) in bindings.iter().enumerate() {
+                                                    if i > 0 { temp.push_str(", ") };
+                                                    temp.push_str(v);
+                                                    temp.push_str(" = ");
+                                                    temp.push_str(&(e.to_string()));
+                                                }
+                                                temp.push_str(") in ");
+                                                temp.push_str(&(body.to_string()));
+                                                temp}
+            LetLangExp::CallExp(rator, rands) => prim_to_string(&(rator.to_string()), &rands),
+            LetLangExp::WhileExp(e1, e2)    => {let mut temp = "while ".to_string();
+                                                temp.push_str(&(e1.to_string()));
+                                                temp.push_str(" do ");
+                                                temp.push_str(&(e2.to_string()));
+                                                temp}
+            LetLangExp::SetExp(v, e)        => {let mut temp = "set ".to_string();
+                                                temp.push_str(&v);
+                                                temp.push_str(" = ");
+                                                temp.push_str(&(e.to_string()));
+                                                temp}
+            LetLangExp::ProcExp(params, body) => format!("proc ({}) {}", params_to_string(&params), body),
+            LetLangExp::QualifiedVarExp(m, v) => format!("from {} take {}", m, v),
+            LetLangExp::NewObjectExp(c, rands) => prim_to_string(&format!("new {}", c), &rands),
+            LetLangExp::MethodCallExp(obj, m, rands) =>
+                                               prim_to_string(&format!("send {} {}", obj, m), &rands),
+            LetLangExp::SuperCallExp(m, rands) => prim_to_string(&format!("super {}", m), &rands),
+            LetLangExp::SelfExp             => "self".to_string(),
+            LetLangExp::CastExp(e, c)       => format!("cast {} {}", e, c),
+            LetLangExp::InstanceOfExp(e, c) => format!("instanceof {} {}", e, c),
+            LetLangExp::PerformExp(op, e)   => format!("perform {}({})", op, e),
+            LetLangExp::HandleExp(e, h)     => {let mut clauses: Vec<String> = h.clauses.iter()
+                                                    .map(|c| format!("{}({}, {}) -> {}", c.op, c.param, c.resume, c.body))
+                                                    .collect();
+                                                if let Some((ref x, ref body)) = h.ret {
+                                                    clauses.push(format!("return({}) -> {}", x, body));
+                                                }
+                                                format!("handle {} with {{{}}}", e, clauses.join(", "))},
+            LetLangExp::GeneratorExp(e)     => format!("generator {}", e),
+            LetLangExp::YieldExp(e)         => format!("yield {}", e),
+            LetLangExp::NextExp(e)          => format!("next({})", e),
+            LetLangExp::IsDoneExp(e)        => format!("done?({})", e),
+            LetLangExp::AmbExp(choices)     => prim_to_string("amb", &choices),
+            LetLangExp::RequireExp(e)       => format!("require({})", e),
+            LetLangExp::CollectAllExp(e)    => format!("collect-all {}", e),
+            LetLangExp::FlipExp(e)          => format!("flip({})", e),
+            LetLangExp::ObserveExp(e)       => format!("observe({})", e),
+            LetLangExp::QuoteExp(e)         => format!("quote {}", e),
+            LetLangExp::SpliceExp(e)        => format!("splice {}", e),
+            LetLangExp::RunExp(e)           => format!("run {}", e),
+            LetLangExp::AssertExp(e, msg)   => format!("assert({}, {})", e, quote_str(&msg)),
+            LetLangExp::ContractLetExp(v, c, e1, e2) => format!("let {} : {} = {} in {}", v, c, e1, e2),
+            LetLangExp::SpannedExp(_, e)    => e.to_string(),
+            LetLangExp::ErrorExp            => "<error>".to_string(),
         }}
 }
 
+impl fmt::Display for Contract {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Contract::Flat(ref pred)          => write!(f, "{}", pred),
+            Contract::Arrow(ref args, ref res) => {
+                let args: Vec<String> = args.iter().map(|c| c.to_string()).collect();
+                if args.is_empty() {
+                    write!(f, "(-> {})", res)
+                } else {
+                    write!(f, "({} -> {})", args.join(", "), res)
+                }},
+        }
+    }}
+
+fn map_all<E, F>(exps: &Vec<Rc<LetLangExp>>, f: &mut F) -> Result<Vec<Rc<LetLangExp>>, E>
+    where F: FnMut(&Rc<LetLangExp>) -> Result<Rc<LetLangExp>, E> {
+    let mut new_exps = Vec::new();
+    for e in exps.iter() {
+        new_exps.push(f(e)?);
+    }
+    Ok(new_exps)
+}
+
+// "name(arg1, arg2, ...)" for the primitives applied with call syntax
+fn prim_to_string(name: &str, args: &[Rc<LetLangExp>]) -> String {
+    let mut temp = name.to_string();
+    temp.push_str("(");
+    for (i, e) in args.iter().enumerate() {
+        if i > 0 { temp.push_str(", ") };
+        temp.push_str(&(e.to_string()));
+    }
+    temp.push_str(")");
+    temp
+}
+
+// AST root: top-level declarations, each visible to the ones after it,
+// followed by an optional main expression
+#[derive(Debug,Clone)]
+pub struct Program {
+    pub defs: Vec<Definition>,
+    pub body: Option<Rc<LetLangExp>>,
+}
+
+#[derive(Debug,Clone)]
+pub enum Definition {
+    ValDefn(String, Rc<LetLangExp>),      // define x = e
+    ModuleDefn(Rc<ModuleDefn>),
+    ClassDefn(Rc<ClassDecl>),
+    InterfaceDefn(Rc<InterfaceDecl>),
+}
+
+// class c extends d implements i ... field x ... method m(params) body ...
+// field, parameter and result types are optional; the checker treats a
+// missing one as any
+#[derive(Debug,Clone)]
+pub struct ClassDecl {
+    pub name: String,
+    pub super_name: String,
+    pub interfaces: Vec<String>,
+    pub fields: Vec<(String, Option<Type>)>,
+    pub methods: Vec<MethodDecl>,
+}
+
+#[derive(Debug,Clone)]
+pub struct MethodDecl {
+    pub sig: MethodSig,
+    pub body: Rc<LetLangExp>,
+}
+
+// method [result type] m(x : type, ...)
+#[derive(Debug,Clone)]
+pub struct MethodSig {
+    pub name: String,
+    pub params: Vec<(String, Option<Type>)>,
+    pub result: Option<Type>,
+}
+
+// interface i method type m(x : type, ...) ...
+#[derive(Debug,Clone)]
+pub struct InterfaceDecl {
+    pub name: String,
+    pub methods: Vec<MethodSig>,
+}
+
+impl MethodSig {
+    pub fn to_string(&self) -> String {
+        let mut temp = "method ".to_string();
+        if let Some(ref t) = self.result {
+            temp.push_str(&(t.to_string()));
+            temp.push_str(" ");
+        }
+        temp.push_str(&self.name);
+        temp.push_str("(");
+        temp.push_str(&(params_to_string(&self.params)));
+        temp.push_str(")");
+        temp
+    }
+}
+
+// "x : int, y" for parameters with optional types
+fn params_to_string(params: &Vec<(String, Option<Type>)>) -> String {
+    let mut temp = String::new();
+    for (i, &(ref v, ref t)) in params.iter().enumerate() {
+        if i > 0 { temp.push_str(", ") };
+        temp.push_str(v);
+        if let Some(ref t) = *t {
+            temp.push_str(" : ");
+            temp.push_str(&(t.to_string()));
+        }
+    }
+    temp
+}
+
+// module m interface [x : t ...] body [x = e ...]
+// body bindings are sequential; only the names in the interface are exported.
+// An interface may also declare types, opaque t or transparent t = type, which
+// the body defines with type t = type.
+#[derive(Debug,Clone)]
+pub struct ModuleDefn {
+    pub name: String,
+    pub iface_types: Vec<(String, Option<Type>)>,   // None for an opaque type
+    pub interface: Vec<(String, Type)>,
+    pub body_types: Vec<(String, Type)>,
+    pub body: Vec<(String, Rc<LetLangExp>)>,
+}
+
+impl Program {
+    pub fn new_program(defs: &Vec<Definition>, body: Option<&LetLangExp>) -> Self {
+        Program { defs: defs.clone(),
+                  body: body.map(|e| Rc::new(e.clone())) }
+    }
+}
+
+impl Definition {
+    pub fn new_val_defn(s: &String, e: &LetLangExp) -> Self {
+        Definition::ValDefn(s.clone(), Rc::new(e.clone()))
+    }
+    pub fn new_module_defn(name: &String,
+                           iface_types: &Vec<(String, Option<Type>)>, interface: &Vec<(String, Type)>,
+                           body_types: &Vec<(String, Type)>, body: &Vec<(String, LetLangExp)>) -> Self {
+        Definition::ModuleDefn(Rc::new(ModuleDefn {
+            name: name.clone(),
+            iface_types: iface_types.clone(),
+            interface: interface.clone(),
+            body_types: body_types.clone(),
+            body: body.iter().map(|&(ref s, ref e)| (s.clone(), Rc::new(e.clone()))).collect() }))
+    }
+    pub fn new_class_defn(decl: &ClassDecl) -> Self {
+        Definition::ClassDefn(Rc::new(decl.clone()))
+    }
+    pub fn new_interface_defn(decl: &InterfaceDecl) -> Self {
+        Definition::InterfaceDefn(Rc::new(decl.clone()))
+    }
+    pub fn to_string(&self) -> String {
+        match *self {
+            Definition::ValDefn(ref s, ref e) => format!("define {} = {}", s, e),
+            Definition::ClassDefn(ref c)      => {let mut temp = format!("class {} extends {}", c.name, c.super_name);
+                                                  for i in c.interfaces.iter() {
+                                                      temp.push_str(&format!(" implements {}", i));
+                                                  }
+                                                  for &(ref f, ref t) in c.fields.iter() {
+                                                      match *t {
+                                                          Some(ref t) => temp.push_str(&format!("\n  field {} {}", t, f)),
+                                                          None        => temp.push_str(&format!("\n  field {}", f)),
+                                                      }
+                                                  }
+                                                  for m in c.methods.iter() {
+                                                      temp.push_str(&format!("\n  {} {}", m.sig.to_string(), m.body));
+                                                  }
+                                                  temp},
+            Definition::InterfaceDefn(ref i)  => {let mut temp = format!("interface {}", i.name);
+                                                  for m in i.methods.iter() {
+                                                      temp.push_str(&format!("\n  {}", m.to_string()));
+                                                  }
+                                                  temp},
+            Definition::ModuleDefn(ref m)     => {let mut temp = "module ".to_string();
+                                                  temp.push_str(&m.name);
+                                                  let mut decls: Vec<String> = Vec::new();
+                                                  for &(ref s, ref t) in m.iface_types.iter() {
+                                                      decls.push(match *t {
+                                                          Some(ref t) => format!("transparent {} = {}", s, t),
+                                                          None        => format!("opaque {}", s),
+                                                      });
+                                                  }
+                                                  for &(ref s, ref t) in m.interface.iter() {
+                                                      decls.push(format!("{} : {}", s, t));
+                                                  }
+                                                  temp.push_str("\n  interface [");
+                                                  temp.push_str(&(decls.join(" ")));
+                                                  let mut defns: Vec<String> = Vec::new();
+                                                  for &(ref s, ref t) in m.body_types.iter() {
+                                                      defns.push(format!("type {} = {}", s, t));
+                                                  }
+                                                  for &(ref s, ref e) in m.body.iter() {
+                                                      defns.push(format!("{} = {}", s, e));
+                                                  }
+                                                  temp.push_str("]\n  body [");
+                                                  temp.push_str(&(defns.join(" ")));
+                                                  temp.push_str("]");
+                                                  temp},
+        }}
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for def in self.defs.iter() {
+            writeln!(f, "{}", def.to_string())?;
+        }
+        if let Some(ref e) = self.body {
+            write!(f, "{}", e)?;
+        }
+        Ok(())
+    }}
+
 impl fmt::Display for LetLangExp { // do not change this code
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut s = format!("");
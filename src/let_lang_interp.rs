@@ -0,0 +1,1501 @@
+// Continuation-passing interpreter for the LET language.
+// value_of_k and apply_cont never call each other directly: each step returns
+// a Bounce to the trampoline in value_of, and the work still to be done is kept
+// in heap-allocated continuations. Evaluation therefore runs in constant Rust
+// stack space, however long a loop or deep a recursion runs. Because the
+// continuation is data, perform can also capture the part of it up to its
+// handler and hand it to the handler as a value.
+
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::vec;
+
+use let_lang_exp::*;
+use let_lang_env::*;
+use let_lang_class::*;
+use let_lang_weight::*;
+use let_lang_scanner::Span;
+use int_bool::*;
+
+// state shared by every expression evaluated in one run of a program;
+// choices is the stack of choice points amb and flip have left to backtrack
+// into, and weight the probability of the flips made on the current path;
+// gensym numbers the variables quote renames; error explains the runtime
+// error that stopped the run, if anything more is known about it than that,
+// and span locates the expression being evaluated
+pub struct Machine {
+    scoping: Scoping,
+    classes: RefCell<HashMap<String, Rc<Class>>>,
+    choices: RefCell<Vec<Choice>>,
+    weight: RefCell<Weight>,
+    gensym: Cell<u32>,
+    error: RefCell<Option<EvalErr>>,
+    span: Cell<Option<Span>>,
+}
+
+// a runtime error, such as a failed assertion or a broken contract, and the
+// source text of the expression that raised it
+pub struct EvalErr {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+// who is at fault when a value breaks a contract: positive for the value
+// itself, negative for the context it was given to. location is the contract
+// as written
+#[derive(Debug,Clone)]
+struct Blame {
+    location: String,
+    span: Option<Span>,
+    positive: String,
+    negative: String,
+}
+
+impl Blame {
+    // the arguments of a procedure flow the other way, from context to procedure
+    fn swap(&self) -> Blame {
+        Blame { location: self.location.clone(), span: self.span, positive: self.negative.clone(),
+                negative: self.positive.clone() }
+    }
+}
+
+// where the free variables of a procedure or method body are looked up:
+// in the environment the procedure was created in, or in the one it is
+// called from. Both extend the same LetLangEnv with the parameters
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Scoping {
+    Lexical,
+    Dynamic,
+}
+
+// a definition, or the main expression, whose value depends on the scoping;
+// None means a runtime error, or that an earlier definition failed
+pub struct ScopingDiff {
+    pub name: String,
+    pub lexical: Option<IntBool>,
+    pub dynamic: Option<IntBool>,
+}
+
+// a choice point: the alternatives of an amb not yet tried, the false branch
+// of a flip, or the start of a collect-all, which ends the search inside it
+// with the values it found. Each restores the weight of its path;
+// backtracking does not undo assignments
+enum Choice {
+    Alternatives(Rc<Vec<Rc<LetLangExp>>>, usize, LetLangEnv, Rc<Cont>, Weight),  // index of the next one
+    Flip(Rc<Cont>, Weight),
+    Collect(Outcomes, Rc<Cont>, Weight),
+}
+
+// the values a search has found, each with the weight of its path
+type Outcomes = Rc<RefCell<Vec<(IntBool, Weight)>>>;
+
+impl Machine {
+    pub fn new_machine() -> Self {
+        Machine::new_machine_scoped(Scoping::Lexical)
+    }
+    pub fn new_machine_scoped(scoping: Scoping) -> Self {
+        let mut classes = HashMap::new();
+        classes.insert("object".to_string(), Rc::new(Class::new_object_class()));
+        Machine { scoping: scoping, classes: RefCell::new(classes), choices: RefCell::new(Vec::new()),
+                  weight: RefCell::new(Weight::one()), gensym: Cell::new(0), error: RefCell::new(None),
+                  span: Cell::new(None) }
+    }
+    fn weight(&self) -> Weight {
+        *self.weight.borrow()
+    }
+    fn set_weight(&self, w: Weight) {
+        *self.weight.borrow_mut() = w;
+    }
+    fn raise(&self, message: String) -> Bounce {
+        *self.error.borrow_mut() = Some(EvalErr { message: message, span: self.span.get() });
+        Bounce::Done(None)
+    }
+    // x#1, x#2, ...: no program can name one, and as # is neither an
+    // identifier char nor a comment leader, printed code holding one does not
+    // lex back, rather than lexing back as some other program
+    fn fresh_name(&self, s: &String) -> String {
+        self.gensym.set(self.gensym.get() + 1);
+        format!("{}#{}", s, self.gensym.get())
+    }
+    fn lookup_class(&self, c: &String) -> Option<Rc<Class>> {
+        self.classes.borrow().get(c).cloned()
+    }
+    // a class must be declared after its superclass
+    fn add_class(&self, decl: &ClassDecl, env: &LetLangEnv) -> Option<()> {
+        let super_class = self.lookup_class(&decl.super_name)?;
+        let class = Class::new_class(decl, &super_class, env);
+        self.classes.borrow_mut().insert(decl.name.clone(), Rc::new(class));
+        Some(())
+    }
+}
+
+// each definition extends the environment seen by the definitions after it;
+// without a main expression the program's value is that of its last define
+pub fn value_of_program(pgm: &Program, env: &LetLangEnv) -> Option<IntBool> {
+    value_of_program_scoped(pgm, env, Scoping::Lexical)
+}
+
+pub fn value_of_program_scoped(pgm: &Program, env: &LetLangEnv, scoping: Scoping) -> Option<IntBool> {
+    value_of_program_on(pgm, env, &Machine::new_machine_scoped(scoping))
+}
+
+// as value_of_program, but says what went wrong
+pub fn eval_program(pgm: &Program, env: &LetLangEnv) -> Result<IntBool, EvalErr> {
+    let mach = Machine::new_machine();
+    match value_of_program_on(pgm, env, &mach) {
+        Some(val) => Ok(val),
+        None      => Err(mach.error.borrow_mut().take()
+                             .unwrap_or(EvalErr { message: "runtime error".to_string(), span: mach.span.get() })),
+    }}
+
+fn value_of_program_on(pgm: &Program, env: &LetLangEnv, mach: &Machine) -> Option<IntBool> {
+    let (pgm_env, last_val) = value_of_defns(pgm, env, mach, &mut |_, _| ())?;
+    match pgm.body {
+        Some(ref e) => run(e, &pgm_env, mach),
+        None        => last_val,
+    }}
+
+// the probabilistic driver: runs the main expression once for every outcome
+// of its flips, and returns each distinct final value with its exact
+// probability given the observations that held. None on a runtime error, or
+// if no run satisfies its observations
+pub fn distribution_of_program(pgm: &Program, env: &LetLangEnv) -> Option<Vec<(IntBool, Weight)>> {
+    let mach = Machine::new_machine();
+    let (pgm_env, _) = value_of_defns(pgm, env, &mach, &mut |_, _| ())?;
+    let body = match pgm.body {
+                   Some(ref e) => e,
+                   None        => return None,
+                   };
+    let mut dist: Vec<(IntBool, Weight)> = Vec::new();
+    let mut total = Weight::new_weight(0, 1);
+    for (val, w) in run_all(body, &pgm_env, &mach)?.into_iter() {
+        total = total.add(&w)?;
+        let found = dist.iter().position(|&(ref v, _)| *v == val);
+        match found {
+            Some(i) => dist[i].1 = dist[i].1.add(&w)?,
+            None    => dist.push((val, w)),
+        }
+    }
+    if total.is_zero() {
+        return None;
+    }
+    let mut normalized = Vec::new();
+    for (val, w) in dist.into_iter() {
+        normalized.push((val, w.div(&total)?));
+    }
+    Some(normalized)
+}
+
+// runs the program under each scoping and lists, in program order, the
+// defines and main expression whose values differ. Values are compared by
+// how they print, so procedures, objects and the like count as equal
+pub fn scoping_diffs(pgm: &Program, env: &LetLangEnv) -> Vec<ScopingDiff> {
+    let mut names: Vec<String> = Vec::new();
+    for def in pgm.defs.iter() {
+        if let Definition::ValDefn(ref s, _) = *def {
+            names.push(s.clone());
+        }
+    }
+    if pgm.body.is_some() {
+        names.push("main expression".to_string());
+    }
+    let lexical = trace_program(pgm, env, Scoping::Lexical);
+    let dynamic = trace_program(pgm, env, Scoping::Dynamic);
+    let mut diffs = Vec::new();
+    for (i, name) in names.into_iter().enumerate() {
+        let l = lexical.get(i).cloned().unwrap_or(None);
+        let d = dynamic.get(i).cloned().unwrap_or(None);
+        if l.as_ref().map(|v| v.to_string()) != d.as_ref().map(|v| v.to_string()) {
+            diffs.push(ScopingDiff { name: name, lexical: l, dynamic: d });
+        }
+    }
+    diffs
+}
+
+// the value of each define, then of the main expression, up to the first error
+fn trace_program(pgm: &Program, env: &LetLangEnv, scoping: Scoping) -> Vec<Option<IntBool>> {
+    let mach = Machine::new_machine_scoped(scoping);
+    let mut trace = Vec::new();
+    let pgm_env = match value_of_defns(pgm, env, &mach, &mut |_, val| trace.push(Some(val.clone()))) {
+                      Some((pgm_env, _)) => pgm_env,
+                      None               => return trace,
+                      };
+    if let Some(ref e) = pgm.body {
+        trace.push(run(e, &pgm_env, &mach));
+    }
+    trace
+}
+
+// record sees the name and value of each define as it is made
+fn value_of_defns(pgm: &Program, env: &LetLangEnv, mach: &Machine, record: &mut dyn FnMut(&String, &IntBool))
+                  -> Option<(LetLangEnv, Option<IntBool>)> {
+    let mut pgm_env = env.clone();
+    let mut last_val = None;
+    for def in pgm.defs.iter() {
+        match *def {
+            Definition::ValDefn(ref s, ref e) => {
+                let val = run(e, &pgm_env, mach)?;
+                record(s, &val);
+                pgm_env = pgm_env.extend_env(s, val.clone());
+                last_val = Some(val);
+            },
+            Definition::ModuleDefn(ref m) => {
+                let exports = value_of_module_body(m, &pgm_env, mach)?;
+                pgm_env = pgm_env.extend_env_module(&m.name, &exports);
+            },
+            Definition::ClassDefn(ref c) => mach.add_class(c, &pgm_env)?,
+            // interfaces only matter to the checker, and to instanceof through
+            // the classes that implement them
+            Definition::InterfaceDefn(_) => (),
+        }
+    }
+    Some((pgm_env, last_val))
+}
+
+// evaluates the body bindings in order; the result binds only the exported names
+fn value_of_module_body(m: &ModuleDefn, env: &LetLangEnv, mach: &Machine) -> Option<LetLangEnv> {
+    let mut body_env = env.clone();
+    for &(ref s, ref e) in m.body.iter() {
+        let val = run(e, &body_env, mach)?;
+        body_env = body_env.extend_env(s, val);
+    }
+    let mut exports = LetLangEnv::new_env();
+    for &(ref s, _) in m.interface.iter() {
+        exports = exports.extend_env(s, body_env.apply_env(s)?);
+    }
+    Some(exports)
+}
+
+pub fn value_of(exp: &LetLangExp, env: &LetLangEnv) -> Option<IntBool> {
+    run(exp, env, &Machine::new_machine())
+}
+
+// a search cannot backtrack into an earlier definition
+fn run(exp: &LetLangExp, env: &LetLangEnv, mach: &Machine) -> Option<IntBool> {
+    mach.choices.borrow_mut().clear();
+    *mach.error.borrow_mut() = None;
+    mach.set_weight(Weight::one());
+    trampoline(value_of_k(exp, env, Rc::new(Cont::EndCont), mach), mach)
+}
+
+// every value exp can produce, as collect-all finds them, with their weights
+fn run_all(exp: &LetLangExp, env: &LetLangEnv, mach: &Machine) -> Option<Vec<(IntBool, Weight)>> {
+    let outcomes = Rc::new(RefCell::new(Vec::new()));
+    mach.choices.borrow_mut().clear();
+    mach.set_weight(Weight::one());
+    mach.choices.borrow_mut().push(Choice::Collect(outcomes.clone(), Rc::new(Cont::EndCont), Weight::one()));
+    trampoline(value_of_k(exp, env, Rc::new(Cont::CollectCont(outcomes.clone())), mach), mach)?;
+    let outcomes = outcomes.borrow().clone();
+    Some(outcomes)
+}
+
+fn trampoline(bounce: Bounce, mach: &Machine) -> Option<IntBool> {
+    let mut bounce = bounce;
+    loop {
+        bounce = match bounce {
+            Bounce::ValueOf(e, env, k) => value_of_k(&e, &env, k, mach),
+            Bounce::ApplyCont(k, val)  => apply_cont(&k, val, mach),
+            Bounce::Done(result)       => return result,
+        }
+    }}
+
+// one step of the machine
+enum Bounce {
+    ValueOf(Rc<LetLangExp>, LetLangEnv, Rc<Cont>),
+    ApplyCont(Rc<Cont>, IntBool),
+    Done(Option<IntBool>),  // None on a runtime error
+}
+
+// operations applied once all of their operands have been evaluated
+#[derive(Debug,Clone)]
+enum Prim {
+    Diff,
+    IsZero,
+    DictLit,                                  // operands alternate key, value
+    DictGet,
+    DictSet,
+    DictHas,
+    DictRemove,
+    DictKeys,
+    Call,                                     // first operand is the procedure
+    NewObject(String),
+    Send(String),                             // first operand is the object
+    Super(String),
+    Cast(String),
+    InstanceOf(String),
+    Perform(String),
+    Yield,
+    Next,
+    IsDone,
+    Require,
+    Flip,
+    Run,
+    NamedLet(String, Vec<String>, Rc<LetLangExp>),  // operands are the initial values
+}
+
+// what remains to be done with the value of the current expression
+#[derive(Debug,Clone)]
+enum Cont {
+    EndCont,
+    OperandsCont(Prim, Rc<Vec<Rc<LetLangExp>>>, Vec<IntBool>, LetLangEnv, Option<Span>, Rc<Cont>),  // span of the whole
+    IfCont(Rc<LetLangExp>, Rc<LetLangExp>, LetLangEnv, Rc<Cont>),
+    LetCont(String, Rc<LetLangExp>, LetLangEnv, Rc<Cont>),
+    BeginCont(Rc<Vec<Rc<LetLangExp>>>, usize, LetLangEnv, Rc<Cont>),  // index of next exp
+    WhileTestCont(Rc<LetLangExp>, Rc<LetLangExp>, LetLangEnv, Rc<Cont>),
+    WhileBodyCont(Rc<LetLangExp>, Rc<LetLangExp>, LetLangEnv, Rc<Cont>),
+    SetCont(String, LetLangEnv, Rc<Cont>),
+    NewObjectCont(IntBool, Rc<Cont>),         // ignores the value of initialize
+    HandleCont(Rc<Handler>, LetLangEnv, Rc<Cont>),  // delimits the continuation a perform captures
+    GeneratorCont(Rc<Generator>, bool, Rc<Cont>),   // delimits a running generator; true for done?
+    CollectCont(Outcomes),                          // records a value, then backtracks for another
+    AssertCont(Rc<LetLangExp>, String, Option<Span>, Rc<Cont>),
+    ContractCont(Rc<Contract>, Rc<Blame>, LetLangEnv, Rc<Cont>),  // env is where the predicates are found
+    PredicateCont(IntBool, Rc<Contract>, Rc<Blame>, Rc<Cont>),    // the value the predicate was applied to
+    GuardArgsCont(Rc<Guarded>, Vec<IntBool>, Vec<IntBool>, LetLangEnv, Rc<Cont>), // checked, then unchecked arguments
+    QuoteCont(Rc<LetLangExp>, Rc<Vec<(Rc<LetLangExp>, LetLangEnv)>>, Vec<IntBool>, Rc<Cont>),  // template, splices, code so far
+}
+
+impl Cont {
+    // the continuation this frame passes its result on to
+    fn next(&self) -> Option<&Rc<Cont>> {
+        match *self {
+            Cont::EndCont | Cont::CollectCont(_) => None,
+            Cont::OperandsCont(_, _, _, _, _, ref k) | Cont::IfCont(_, _, _, ref k) | Cont::LetCont(_, _, _, ref k)
+            | Cont::BeginCont(_, _, _, ref k) | Cont::WhileTestCont(_, _, _, ref k)
+            | Cont::WhileBodyCont(_, _, _, ref k) | Cont::SetCont(_, _, ref k) | Cont::NewObjectCont(_, ref k)
+            | Cont::HandleCont(_, _, ref k) | Cont::GeneratorCont(_, _, ref k) | Cont::AssertCont(_, _, _, ref k)
+            | Cont::ContractCont(_, _, _, ref k) | Cont::PredicateCont(_, _, _, ref k)
+            | Cont::GuardArgsCont(_, _, _, _, ref k) | Cont::QuoteCont(_, _, _, ref k) => Some(k),
+        }}
+    // a copy of this frame that passes its result on to k instead
+    fn with_next(&self, k: Rc<Cont>) -> Cont {
+        match self.clone() {
+            Cont::EndCont                          => Cont::EndCont,
+            Cont::CollectCont(vals)                => Cont::CollectCont(vals),
+            Cont::OperandsCont(p, exps, vals, env, span, _) => Cont::OperandsCont(p, exps, vals, env, span, k),
+            Cont::IfCont(e2, e3, env, _)           => Cont::IfCont(e2, e3, env, k),
+            Cont::LetCont(s, body, env, _)         => Cont::LetCont(s, body, env, k),
+            Cont::BeginCont(exps, i, env, _)       => Cont::BeginCont(exps, i, env, k),
+            Cont::WhileTestCont(test, body, env, _) => Cont::WhileTestCont(test, body, env, k),
+            Cont::WhileBodyCont(test, body, env, _) => Cont::WhileBodyCont(test, body, env, k),
+            Cont::SetCont(s, env, _)               => Cont::SetCont(s, env, k),
+            Cont::NewObjectCont(obj, _)            => Cont::NewObjectCont(obj, k),
+            Cont::HandleCont(h, env, _)            => Cont::HandleCont(h, env, k),
+            Cont::GeneratorCont(g, peek, _)        => Cont::GeneratorCont(g, peek, k),
+            Cont::AssertCont(test, msg, span, _)   => Cont::AssertCont(test, msg, span, k),
+            Cont::ContractCont(c, blame, env, _)   => Cont::ContractCont(c, blame, env, k),
+            Cont::PredicateCont(val, c, blame, _)  => Cont::PredicateCont(val, c, blame, k),
+            Cont::GuardArgsCont(g, checked, rest, env, _) => Cont::GuardArgsCont(g, checked, rest, env, k),
+            Cont::QuoteCont(template, holes, vals, _) => Cont::QuoteCont(template, holes, vals, k),
+        }}
+}
+
+// a procedure bound by a let with an arrow contract; every call checks the
+// arguments, then the result
+pub struct Guarded {
+    proc: IntBool,
+    args: Vec<Rc<Contract>>,
+    result: Rc<Contract>,
+    env: LetLangEnv,
+    blame: Rc<Blame>,
+}
+
+// the continuation of a perform as a first-class value: the frames from the
+// perform out to and including the handle that caught it, innermost first.
+// Resuming copies them onto the caller's continuation, so the handler stays
+// installed and k may be resumed any number of times
+pub struct Continuation {
+    frames: Vec<Rc<Cont>>,
+}
+
+// a generator runs its body only when asked for a value, by next or done?,
+// and only up to the following yield
+pub struct Generator {
+    state: RefCell<GenState>,
+}
+
+enum GenState {
+    Start(Rc<LetLangExp>, LetLangEnv),
+    Suspended(IntBool, Vec<Rc<Cont>>),  // at a yield: its value and the frames out to the generator
+    Ready(IntBool, Vec<Rc<Cont>>),      // at a yield done? has seen, but next has not yet returned
+    Running,
+    Finished,
+}
+
+impl Generator {
+    fn new_generator(body: &Rc<LetLangExp>, env: &LetLangEnv) -> Self {
+        Generator { state: RefCell::new(GenState::Start(body.clone(), env.clone())) }
+    }
+}
+
+impl PartialEq for Generator {
+    fn eq(&self, other: &Generator) -> bool {
+        self as *const Generator == other as *const Generator
+    }}
+impl fmt::Debug for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<generator>")
+    }}
+impl PartialEq for Continuation {
+    fn eq(&self, other: &Continuation) -> bool {
+        self as *const Continuation == other as *const Continuation
+    }}
+impl fmt::Display for ScopingDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |v: &Option<IntBool>| match *v {
+                       Some(ref v) => v.to_string(),
+                       None        => "error".to_string(),
+                       };
+        write!(f, "{}: lexical {}, dynamic {}", self.name, show(&self.lexical), show(&self.dynamic))
+    }}
+impl PartialEq for Guarded {
+    fn eq(&self, other: &Guarded) -> bool {
+        self as *const Guarded == other as *const Guarded
+    }}
+impl fmt::Display for Guarded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.proc.to_string())
+    }}
+impl fmt::Debug for Guarded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<guarded {}>", self)
+    }}
+impl fmt::Display for EvalErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(ref span) => write!(f, "EvalError: {} ({})", self.message, span),
+            None           => write!(f, "EvalError: {}", self.message),
+        }
+    }}
+impl fmt::Debug for EvalErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }}
+impl fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<continuation>")
+    }}
+
+fn value_of_k(exp: &LetLangExp, env: &LetLangEnv, cont: Rc<Cont>, mach: &Machine) -> Bounce {
+    match exp.clone() {
+        LetLangExp::ConstExp(int)    => Bounce::ApplyCont(cont, IntBool::Integer(int)),
+        LetLangExp::Boolean(b)       => Bounce::ApplyCont(cont, IntBool::Boolean(b)),
+        LetLangExp::StrExp(s)        => Bounce::ApplyCont(cont, IntBool::Str(s)),
+        LetLangExp::VarExp(s)        => match env.apply_env(&s) {
+                                            Some(val) => Bounce::ApplyCont(cont, val),
+                                            None      => Bounce::Done(None),
+                                            },
+        LetLangExp::DiffExp(e1, e2)  => eval_operands(Prim::Diff, vec![e1, e2], env, cont, mach),
+        LetLangExp::IsZeroExp(e)     => eval_operands(Prim::IsZero, vec![e], env, cont, mach),
+        LetLangExp::IfExp(e1,e2,e3)  => Bounce::ValueOf(e1, env.clone(),
+                                            Rc::new(Cont::IfCont(e2, e3, env.clone(), cont))),
+        LetLangExp::LetExp(s,e1,e2)  => Bounce::ValueOf(e1, env.clone(),
+                                            Rc::new(Cont::LetCont(s, e2, env.clone(), cont))),
+        LetLangExp::DictExp(entries) => {
+            let mut exps = Vec::new();
+            for (k, v) in entries.into_iter() {
+                exps.push(k);
+                exps.push(v);
+            }
+            eval_operands(Prim::DictLit, exps, env, cont, mach)},
+        LetLangExp::DictGetExp(d,k)    => eval_operands(Prim::DictGet, vec![d, k], env, cont, mach),
+        LetLangExp::DictSetExp(d,k,v)  => eval_operands(Prim::DictSet, vec![d, k, v], env, cont, mach),
+        LetLangExp::DictHasExp(d,k)    => eval_operands(Prim::DictHas, vec![d, k], env, cont, mach),
+        LetLangExp::DictRemoveExp(d,k) => eval_operands(Prim::DictRemove, vec![d, k], env, cont, mach),
+        LetLangExp::DictKeysExp(d)     => eval_operands(Prim::DictKeys, vec![d], env, cont, mach),
+        LetLangExp::BeginExp(exps)     => value_of_sequence(Rc::new(exps), 0, env, cont),
+        LetLangExp::NamedLetExp(name, bindings, body) => {
+            let (params, inits): (Vec<String>, Vec<Rc<LetLangExp>>) = bindings.into_iter().unzip();
+            eval_operands(Prim::NamedLet(name, params, body), inits, env, cont, mach)},
+        LetLangExp::CallExp(rator, rands) => {
+            let mut exps = vec![rator];
+            exps.extend(rands.into_iter());
+            eval_operands(Prim::Call, exps, env, cont, mach)},
+        LetLangExp::WhileExp(test, body) => Bounce::ValueOf(test.clone(), env.clone(),
+                                            Rc::new(Cont::WhileTestCont(test, body, env.clone(), cont))),
+        LetLangExp::SetExp(s, e)     => Bounce::ValueOf(e, env.clone(),
+                                            Rc::new(Cont::SetCont(s, env.clone(), cont))),
+        LetLangExp::ProcExp(params, body) => {
+            let names: Vec<String> = params.into_iter().map(|(v, _)| v).collect();
+            Bounce::ApplyCont(cont, IntBool::Proc(Rc::new(Proc::new_proc(None, &names, &body, env))))},
+        LetLangExp::QualifiedVarExp(m, s) => match env.apply_env_qualified(&m, &s) {
+                                            Some(val) => Bounce::ApplyCont(cont, val),
+                                            None      => Bounce::Done(None),
+                                            },
+        LetLangExp::NewObjectExp(c, rands) => eval_operands(Prim::NewObject(c), rands, env, cont, mach),
+        LetLangExp::MethodCallExp(obj, m, rands) => {
+            let mut exps = vec![obj];
+            exps.extend(rands.into_iter());
+            eval_operands(Prim::Send(m), exps, env, cont, mach)},
+        LetLangExp::SuperCallExp(m, rands) => eval_operands(Prim::Super(m), rands, env, cont, mach),
+        LetLangExp::SelfExp          => match env.apply_env(&("self".to_string())) {
+                                            Some(val) => Bounce::ApplyCont(cont, val),
+                                            None      => Bounce::Done(None),
+                                            },
+        LetLangExp::CastExp(e, c)       => eval_operands(Prim::Cast(c), vec![e], env, cont, mach),
+        LetLangExp::InstanceOfExp(e, c) => eval_operands(Prim::InstanceOf(c), vec![e], env, cont, mach),
+        LetLangExp::PerformExp(op, e)   => eval_operands(Prim::Perform(op), vec![e], env, cont, mach),
+        LetLangExp::HandleExp(e, h)     => Bounce::ValueOf(e, env.clone(),
+                                            Rc::new(Cont::HandleCont(h, env.clone(), cont))),
+        LetLangExp::GeneratorExp(e)     =>
+            Bounce::ApplyCont(cont, IntBool::Generator(Rc::new(Generator::new_generator(&e, env)))),
+        LetLangExp::YieldExp(e)         => eval_operands(Prim::Yield, vec![e], env, cont, mach),
+        LetLangExp::NextExp(e)          => eval_operands(Prim::Next, vec![e], env, cont, mach),
+        LetLangExp::IsDoneExp(e)        => eval_operands(Prim::IsDone, vec![e], env, cont, mach),
+        LetLangExp::AmbExp(choices)     => {
+            let choices = Rc::new(choices);
+            try_choice(&choices, 0, env, cont, mach)},
+        LetLangExp::RequireExp(e)       => eval_operands(Prim::Require, vec![e], env, cont, mach),
+        LetLangExp::CollectAllExp(e)    => {
+            let outcomes = Rc::new(RefCell::new(Vec::new()));
+            mach.choices.borrow_mut().push(Choice::Collect(outcomes.clone(), cont, mach.weight()));
+            Bounce::ValueOf(e, env.clone(), Rc::new(Cont::CollectCont(outcomes)))},
+        LetLangExp::FlipExp(e)          => eval_operands(Prim::Flip, vec![e], env, cont, mach),
+        LetLangExp::ObserveExp(e)       => eval_operands(Prim::Require, vec![e], env, cont, mach),
+        LetLangExp::QuoteExp(e)         => {
+            let mut holes = Vec::new();
+            match quote_template(&e, 1, env, mach, &mut holes) {
+                Ok(template) => next_splice(template, Rc::new(holes), Vec::new(), cont),
+                Err(())      => Bounce::Done(None),
+            }},
+        LetLangExp::SpliceExp(_)        => Bounce::Done(None),  // outside of any quote
+        LetLangExp::RunExp(e)           => eval_operands(Prim::Run, vec![e], env, cont, mach),
+        LetLangExp::SpannedExp(span, e) => {
+            mach.span.set(Some(span));
+            value_of_k(&e, env, cont, mach)},
+        LetLangExp::ErrorExp            => mach.raise("syntax error".to_string()),
+        LetLangExp::AssertExp(test, msg) => Bounce::ValueOf(test.clone(), env.clone(),
+                                            Rc::new(Cont::AssertCont(test, msg, mach.span.get(), cont))),
+        // the bound expression answers for the value, the body for how it uses it
+        LetLangExp::ContractLetExp(s, c, e1, e2) => {
+            let blame = Blame { location: format!("let {} : {}", s, c), span: mach.span.get(),
+                                positive: format!("the expression bound to {}", s),
+                                negative: format!("the body of let {}", s) };
+            Bounce::ValueOf(e1, env.clone(),
+                            Rc::new(Cont::ContractCont(c, Rc::new(blame), env.clone(),
+                                                       Rc::new(Cont::LetCont(s, e2, env.clone(), cont)))))},
+    }}
+
+// evaluate exps left to right, then apply prim to their values
+fn eval_operands(prim: Prim, exps: Vec<Rc<LetLangExp>>, env: &LetLangEnv, cont: Rc<Cont>, mach: &Machine) -> Bounce {
+    if exps.is_empty() {
+        return apply_prim(&prim, Vec::new(), env, cont, mach);
+    }
+    let first = exps[0].clone();
+    Bounce::ValueOf(first, env.clone(),
+                    Rc::new(Cont::OperandsCont(prim, Rc::new(exps), Vec::new(), env.clone(), mach.span.get(), cont)))
+}
+
+// evaluate exps[i..]; the last one is evaluated with cont itself, in tail position
+fn value_of_sequence(exps: Rc<Vec<Rc<LetLangExp>>>, i: usize, env: &LetLangEnv, cont: Rc<Cont>) -> Bounce {
+    let e = exps[i].clone();
+    if i + 1 == exps.len() {
+        Bounce::ValueOf(e, env.clone(), cont)
+    } else {
+        Bounce::ValueOf(e, env.clone(), Rc::new(Cont::BeginCont(exps, i + 1, env.clone(), cont)))
+    }}
+
+fn apply_cont(cont: &Cont, val: IntBool, mach: &Machine) -> Bounce {
+    match *cont {
+        Cont::EndCont => Bounce::Done(Some(val)),
+        Cont::OperandsCont(ref prim, ref exps, ref vals, ref env, span, ref k) => {
+            let mut vals = vals.clone();
+            vals.push(val);
+            if vals.len() < exps.len() {
+                let next = exps[vals.len()].clone();
+                Bounce::ValueOf(next, env.clone(),
+                                Rc::new(Cont::OperandsCont(prim.clone(), exps.clone(), vals, env.clone(), span, k.clone())))
+            } else {
+                mach.span.set(span);
+                apply_prim(prim, vals, env, k.clone(), mach)
+            }},
+        // treat the test as true only if it is boolean true
+        Cont::IfCont(ref e2, ref e3, ref env, ref k) => match val {
+            IntBool::Boolean(true) => Bounce::ValueOf(e2.clone(), env.clone(), k.clone()),
+            _                      => Bounce::ValueOf(e3.clone(), env.clone(), k.clone()),
+            },
+        Cont::LetCont(ref s, ref body, ref env, ref k) =>
+            Bounce::ValueOf(body.clone(), env.extend_env(s, val), k.clone()),
+        Cont::BeginCont(ref exps, i, ref env, ref k) => value_of_sequence(exps.clone(), i, env, k.clone()),
+        // a while loop's value is that of its final test, false
+        Cont::WhileTestCont(ref test, ref body, ref env, ref k) => match val {
+            IntBool::Boolean(true)  => Bounce::ValueOf(body.clone(), env.clone(),
+                                        Rc::new(Cont::WhileBodyCont(test.clone(), body.clone(), env.clone(), k.clone()))),
+            IntBool::Boolean(false) => Bounce::ApplyCont(k.clone(), val),
+            _                       => Bounce::Done(None),
+            },
+        Cont::WhileBodyCont(ref test, ref body, ref env, ref k) =>
+            Bounce::ValueOf(test.clone(), env.clone(),
+                            Rc::new(Cont::WhileTestCont(test.clone(), body.clone(), env.clone(), k.clone()))),
+        Cont::SetCont(ref s, ref env, ref k) =>
+            if env.set_env(s, val.clone()) {
+                Bounce::ApplyCont(k.clone(), val)
+            } else {
+                Bounce::Done(None)
+            },
+        Cont::NewObjectCont(ref obj, ref k) => Bounce::ApplyCont(k.clone(), obj.clone()),
+        // the handled expression returned normally
+        Cont::HandleCont(ref h, ref env, ref k) => match h.ret {
+            Some((ref x, ref body)) => Bounce::ValueOf(body.clone(), env.extend_env(x, val), k.clone()),
+            None                    => Bounce::ApplyCont(k.clone(), val),
+            },
+        Cont::CollectCont(ref outcomes) => {
+            outcomes.borrow_mut().push((val, mach.weight()));
+            backtrack(mach)},
+        Cont::AssertCont(ref test, ref msg, span, ref k) => match val {
+            IntBool::Boolean(true) => Bounce::ApplyCont(k.clone(), val),
+            _                      => { mach.span.set(span); mach.raise(format!("assertion failed: {}; blaming assert({}, {})",
+                                                         msg, test, quote_str(msg))) },
+            },
+        Cont::ContractCont(ref c, ref blame, ref env, ref k) => monitor(val, c, blame, env, k.clone(), mach),
+        Cont::PredicateCont(ref checked, ref c, ref blame, ref k) => match val {
+            IntBool::Boolean(true) => Bounce::ApplyCont(k.clone(), checked.clone()),
+            _                      => violation(checked, c, blame, mach),
+            },
+        Cont::GuardArgsCont(ref g, ref checked, ref rest, ref env, ref k) => {
+            let mut checked = checked.clone();
+            checked.push(val);
+            check_args(g, checked, rest.clone(), env, k.clone(), mach)},
+        Cont::QuoteCont(ref template, ref holes, ref vals, ref k) => {
+            let mut vals = vals.clone();
+            vals.push(val);
+            next_splice(template.clone(), holes.clone(), vals, k.clone())},
+        // the generator's body returned: there are no more values
+        Cont::GeneratorCont(ref g, peek, ref k) => {
+            *g.state.borrow_mut() = GenState::Finished;
+            if peek {
+                Bounce::ApplyCont(k.clone(), IntBool::Boolean(true))
+            } else {
+                Bounce::Done(None)
+            }},
+    }}
+
+fn apply_prim(prim: &Prim, vals: Vec<IntBool>, env: &LetLangEnv, cont: Rc<Cont>, mach: &Machine) -> Bounce {
+    let result = match *prim {
+        Prim::Diff       => match value_of_diff_exp_work(&vals[0], &vals[1]) {
+                                Some(i) => Some(IntBool::Integer(i)),
+                                None    => return mach.raise(format!("integer overflow in -({}, {})",
+                                                                     vals[0].to_string(), vals[1].to_string())),
+                                },
+        Prim::IsZero     => match vals[0] {
+                                IntBool::Integer(i) => Some(IntBool::Boolean(i == 0)),
+                                _                   => None,
+                                },
+        Prim::DictLit    => value_of_dict(&vals),
+        Prim::DictGet    => dict_prim(&vals[0], &vals[1], |map, key| map.get(&key).cloned()),
+        Prim::DictSet    => dict_prim(&vals[0], &vals[1], |map, key| {
+                                let mut new_map = map.clone();
+                                new_map.insert(key, vals[2].clone());
+                                Some(IntBool::Dict(Rc::new(new_map)))
+                                }),
+        Prim::DictHas    => dict_prim(&vals[0], &vals[1],
+                                      |map, key| Some(IntBool::Boolean(map.contains_key(&key)))),
+        Prim::DictRemove => dict_prim(&vals[0], &vals[1], |map, key| {
+                                let mut new_map = map.clone();
+                                new_map.remove(&key);
+                                Some(IntBool::Dict(Rc::new(new_map)))
+                                }),
+        Prim::DictKeys   => match vals[0] {
+                                IntBool::Dict(ref map) =>
+                                    Some(IntBool::List(Rc::new(map.keys().map(|k| k.to_val()).collect()))),
+                                _                      => None,
+                                },
+        Prim::Call       => return apply_callable(&vals[0], &vals[1..], env, cont, mach),
+        Prim::Perform(ref op) => return perform(op, vals[0].clone(), cont),
+        Prim::Yield      => return yield_value(vals[0].clone(), cont),
+        Prim::Next       => return match vals[0] {
+                                IntBool::Generator(ref g) => advance(g, false, cont),
+                                _                         => Bounce::Done(None),
+                                },
+        Prim::IsDone     => return match vals[0] {
+                                IntBool::Generator(ref g) => advance(g, true, cont),
+                                _                         => Bounce::Done(None),
+                                },
+        // require and observe: a failed test abandons the current path
+        Prim::Require    => match vals[0] {
+                                IntBool::Boolean(true)  => Some(vals[0].clone()),
+                                IntBool::Boolean(false) => return backtrack(mach),
+                                _                       => None,
+                                },
+        // code runs in an empty environment
+        Prim::Run        => return match vals[0] {
+                                IntBool::Code(ref code) => Bounce::ValueOf(code.clone(), LetLangEnv::new_env(), cont),
+                                _                       => Bounce::Done(None),
+                                },
+        Prim::Flip       => return match vals[0] {
+                                IntBool::Integer(p) if (0..=100).contains(&p) => flip(p as u128, cont, mach),
+                                ref v                                         =>
+                                    mach.raise(format!("flip takes a percent from 0 to 100, not {}", v.to_string())),
+                                },
+        // the loop procedure can see itself, under either scoping; the initial
+        // values were computed outside it
+        Prim::NamedLet(ref name, ref params, ref body) => {
+            let loop_env = env.extend_env_rec(name, params, body);
+            let p = Proc::new_proc(Some(name), params, body, &loop_env);
+            return apply_procedure(&p, &vals, &loop_env, cont, mach)},
+        // initialize, if the class has one, runs on the new object before it is returned
+        Prim::NewObject(ref c) => {
+            let class = match mach.lookup_class(c) {
+                            Some(class) => class,
+                            None        => return Bounce::Done(None),
+                            };
+            let obj = Rc::new(Object::new_object(&class));
+            return match class.find_method(&("initialize".to_string())) {
+                Some(init) => apply_method(&init, &obj, &vals, env,
+                                           Rc::new(Cont::NewObjectCont(IntBool::Object(obj.clone()), cont)), mach),
+                None if vals.is_empty() => Bounce::ApplyCont(cont, IntBool::Object(obj)),
+                None       => Bounce::Done(None),
+                }},
+        Prim::Send(ref m) => return match vals[0] {
+            IntBool::Object(ref obj) => match obj.class.find_method(m) {
+                Some(method) => apply_method(&method, obj, &vals[1..], env, cont, mach),
+                None         => Bounce::Done(None),
+                },
+            _                        => Bounce::Done(None),
+            },
+        // dispatch starts at the superclass of the class the running method was declared in
+        Prim::Super(ref m) => {
+            let super_name = env.apply_env(&("%super".to_string()));
+            let obj = env.apply_env(&("self".to_string()));
+            return match (super_name, obj) {
+                (Some(IntBool::Str(super_name)), Some(IntBool::Object(obj))) =>
+                    match mach.lookup_class(&super_name).and_then(|class| class.find_method(m)) {
+                        Some(method) => apply_method(&method, &obj, &vals, env, cont, mach),
+                        None         => Bounce::Done(None),
+                    },
+                _ => Bounce::Done(None),
+                }},
+        // a failed cast is a runtime error
+        Prim::Cast(ref c) => match vals[0] {
+                                IntBool::Object(ref obj) if obj.class.is_subclass_of(c) => Some(vals[0].clone()),
+                                _                                                     => None,
+                                },
+        Prim::InstanceOf(ref c) => match vals[0] {
+                                IntBool::Object(ref obj) => Some(IntBool::Boolean(obj.class.is_subclass_of(c))),
+                                _                        => None,
+                                },
+    };
+    match result {
+        Some(val) => Bounce::ApplyCont(cont, val),
+        None      => Bounce::Done(None),
+    }}
+
+// the body runs with the caller's continuation, so calls in tail position do
+// not grow it; under dynamic scoping it also runs in the caller's environment
+fn apply_procedure(p: &Proc, args: &[IntBool], caller_env: &LetLangEnv, cont: Rc<Cont>, mach: &Machine) -> Bounce {
+    if p.params.len() != args.len() {
+        return Bounce::Done(None);
+    }
+    let mut new_env = match mach.scoping {
+                          Scoping::Lexical => p.env.clone(),
+                          Scoping::Dynamic => caller_env.clone(),
+                          };
+    for (param, arg) in p.params.iter().zip(args.iter()) {
+        new_env = new_env.extend_env(param, arg.clone());
+    }
+    Bounce::ValueOf(p.body.clone(), new_env, cont)
+}
+
+// procedures, guarded procedures and continuations can all be called
+fn apply_callable(f: &IntBool, args: &[IntBool], caller_env: &LetLangEnv, cont: Rc<Cont>, mach: &Machine) -> Bounce {
+    match *f {
+        IntBool::Proc(ref p)                     => apply_procedure(p, args, caller_env, cont, mach),
+        IntBool::Guarded(ref g)                  => check_args(g, Vec::new(), args.to_vec(), caller_env, cont, mach),
+        IntBool::Cont(ref k) if args.len() == 1 => resume(k, args[0].clone(), cont),
+        _                                        => Bounce::Done(None),
+    }}
+
+// passes val on to cont if it satisfies contract c; a procedure under an
+// arrow contract is passed on guarded
+fn monitor(val: IntBool, c: &Rc<Contract>, blame: &Rc<Blame>, env: &LetLangEnv, cont: Rc<Cont>, mach: &Machine) -> Bounce {
+    match **c {
+        // a procedure in scope takes precedence over a built-in predicate
+        Contract::Flat(ref pred) => match env.apply_env(pred) {
+            Some(p) => apply_callable(&p, &[val.clone()], env,
+                                      Rc::new(Cont::PredicateCont(val, c.clone(), blame.clone(), cont)), mach),
+            None    => match builtin_predicate(pred, &val) {
+                Some(true)  => Bounce::ApplyCont(cont, val),
+                Some(false) => violation(&val, c, blame, mach),
+                None        => { mach.span.set(blame.span);
+                                 mach.raise(format!("unknown contract {} in {}", pred, blame.location)) },
+                },
+            },
+        Contract::Arrow(ref args, ref result) => match val {
+            IntBool::Proc(_) | IntBool::Guarded(_) | IntBool::Cont(_) => {
+                let g = Guarded { proc: val, args: args.clone(), result: result.clone(), env: env.clone(),
+                                  blame: blame.clone() };
+                Bounce::ApplyCont(cont, IntBool::Guarded(Rc::new(g)))},
+            _ => violation(&val, c, blame, mach),
+            },
+    }}
+
+// checks the remaining arguments of a call of g in order, then calls it
+// with the result checked on the way out
+fn check_args(g: &Rc<Guarded>, checked: Vec<IntBool>, rest: Vec<IntBool>, caller_env: &LetLangEnv, cont: Rc<Cont>,
+              mach: &Machine) -> Bounce {
+    if checked.len() + rest.len() != g.args.len() {
+        mach.span.set(g.blame.span);
+        return mach.raise(format!("contract violation: {} arguments given to a procedure of {} in {}; blaming {}",
+                                  checked.len() + rest.len(), g.args.len(), g.blame.location, g.blame.negative));
+    }
+    match rest.split_first() {
+        None => apply_callable(&g.proc, &checked, caller_env,
+                               Rc::new(Cont::ContractCont(g.result.clone(), g.blame.clone(), g.env.clone(), cont)),
+                               mach),
+        Some((arg, rest)) => {
+            let c = g.args[checked.len()].clone();
+            let k = Rc::new(Cont::GuardArgsCont(g.clone(), checked, rest.to_vec(), caller_env.clone(), cont));
+            monitor(arg.clone(), &c, &Rc::new(g.blame.swap()), &g.env, k, mach)},
+    }}
+
+fn violation(val: &IntBool, c: &Contract, blame: &Blame, mach: &Machine) -> Bounce {
+    mach.span.set(blame.span);
+    mach.raise(format!("contract violation: {} does not satisfy {} in {}; blaming {}",
+                       val.to_string(), c, blame.location, blame.positive))
+}
+
+// None if there is no predicate of that name
+fn builtin_predicate(pred: &str, val: &IntBool) -> Option<bool> {
+    let int_test = |test: fn(i32) -> bool| match *val {
+                       IntBool::Integer(i) => test(i),
+                       _                   => false,
+                       };
+    Some(match pred {
+        "positive?" => int_test(|i| i > 0),
+        "negative?" => int_test(|i| i < 0),
+        "zero?"     => int_test(|i| i == 0),
+        "even?"     => int_test(|i| i % 2 == 0),
+        "odd?"      => int_test(|i| i % 2 != 0),
+        "int?"      => int_test(|_| true),
+        "bool?"     => match *val { IntBool::Boolean(_) => true, _ => false },
+        "string?"   => match *val { IntBool::Str(_) => true, _ => false },
+        "proc?"     => match *val { IntBool::Proc(_) | IntBool::Guarded(_) | IntBool::Cont(_) => true, _ => false },
+        "any?"      => true,
+        _           => return None,
+    })
+}
+
+// splits cont at the nearest frame for which found holds: the frames inside
+// it, innermost first, and the frame itself
+fn capture<F>(cont: Rc<Cont>, found: F) -> Option<(Vec<Rc<Cont>>, Rc<Cont>)> where F: Fn(&Cont) -> bool {
+    let mut frames = Vec::new();
+    let mut k = cont;
+    while !found(&k) {
+        let next = k.next()?.clone();
+        frames.push(k);
+        k = next;
+    }
+    Some((frames, k))
+}
+
+// copies frames, innermost first, onto cont
+fn reinstall(frames: &[Rc<Cont>], cont: Rc<Cont>) -> Rc<Cont> {
+    let mut tail = cont;
+    for frame in frames.iter().rev() {
+        tail = Rc::new(frame.with_next(tail));
+    }
+    tail
+}
+
+// finds the nearest handle with a clause for op; the clause runs in the
+// continuation of the handle itself, so an operation it performs goes to an
+// outer handler. Unhandled operations are errors
+fn perform(op: &String, val: IntBool, cont: Rc<Cont>) -> Bounce {
+    let handles_op = |k: &Cont| match *k {
+                         Cont::HandleCont(ref h, _, _) => h.clauses.iter().any(|c| c.op == *op),
+                         _                             => false,
+                         };
+    let (mut frames, handle) = match capture(cont, handles_op) {
+                                   Some(split) => split,
+                                   None        => return Bounce::Done(None),
+                                   };
+    frames.push(handle.clone());
+    match *handle {
+        Cont::HandleCont(ref h, ref env, ref outer) => {
+            let clause = h.clauses.iter().find(|c| c.op == *op).unwrap();
+            let resumption = IntBool::Cont(Rc::new(Continuation { frames: frames }));
+            let clause_env = env.extend_env(&clause.param, val).extend_env(&clause.resume, resumption);
+            Bounce::ValueOf(clause.body.clone(), clause_env, outer.clone())},
+        _ => Bounce::Done(None),
+    }}
+
+// the value becomes that of the perform; the handle then returns to cont
+fn resume(k: &Continuation, val: IntBool, cont: Rc<Cont>) -> Bounce {
+    Bounce::ApplyCont(reinstall(&k.frames, cont), val)
+}
+
+// runs g up to its next yield, or to its end; next returns the yielded value,
+// done? only whether there is one and keeps it for the next call of next
+fn advance(g: &Rc<Generator>, peek: bool, cont: Rc<Cont>) -> Bounce {
+    let state = ::std::mem::replace(&mut *g.state.borrow_mut(), GenState::Running);
+    match state {
+        GenState::Start(body, env)       =>
+            Bounce::ValueOf(body, env, Rc::new(Cont::GeneratorCont(g.clone(), peek, cont))),
+        // the yield expression's own value is the value it yielded
+        GenState::Suspended(val, frames) =>
+            Bounce::ApplyCont(reinstall(&frames, Rc::new(Cont::GeneratorCont(g.clone(), peek, cont))), val),
+        GenState::Ready(val, frames)     => if peek {
+                                                *g.state.borrow_mut() = GenState::Ready(val, frames);
+                                                Bounce::ApplyCont(cont, IntBool::Boolean(false))
+                                            } else {
+                                                *g.state.borrow_mut() = GenState::Suspended(val.clone(), frames);
+                                                Bounce::ApplyCont(cont, val)
+                                            },
+        GenState::Finished               => {
+            *g.state.borrow_mut() = GenState::Finished;
+            if peek { Bounce::ApplyCont(cont, IntBool::Boolean(true)) } else { Bounce::Done(None) }},
+        // a generator asked for its own next value
+        GenState::Running                => Bounce::Done(None),
+    }}
+
+// suspends the nearest running generator
+fn yield_value(val: IntBool, cont: Rc<Cont>) -> Bounce {
+    let is_generator = |k: &Cont| match *k {
+                           Cont::GeneratorCont(..) => true,
+                           _                       => false,
+                           };
+    let (frames, delimiter) = match capture(cont, is_generator) {
+                                  Some(split) => split,
+                                  None        => return Bounce::Done(None),
+                                  };
+    match *delimiter {
+        Cont::GeneratorCont(ref g, true, ref k)  => {
+            *g.state.borrow_mut() = GenState::Ready(val, frames);
+            Bounce::ApplyCont(k.clone(), IntBool::Boolean(false))},
+        Cont::GeneratorCont(ref g, false, ref k) => {
+            *g.state.borrow_mut() = GenState::Suspended(val.clone(), frames);
+            Bounce::ApplyCont(k.clone(), val)},
+        _ => Bounce::Done(None),
+    }}
+
+// evaluates choices[i], leaving a choice point for the ones after it; an amb
+// with no choices left fails
+fn try_choice(choices: &Rc<Vec<Rc<LetLangExp>>>, i: usize, env: &LetLangEnv, cont: Rc<Cont>, mach: &Machine) -> Bounce {
+    if i == choices.len() {
+        return backtrack(mach);
+    }
+    if i + 1 < choices.len() {
+        mach.choices.borrow_mut().push(Choice::Alternatives(choices.clone(), i + 1, env.clone(), cont.clone(),
+                                                            mach.weight()));
+    }
+    Bounce::ValueOf(choices[i].clone(), env.clone(), cont)
+}
+
+// true with probability p percent: continues with true, leaving the false
+// branch as a choice point; a branch of probability 0 is never taken
+fn flip(p: u128, cont: Rc<Cont>, mach: &Machine) -> Bounce {
+    let w = mach.weight();
+    let (w_true, w_false) = match (w.mul(&Weight::new_weight(p, 100)), w.mul(&Weight::new_weight(100 - p, 100))) {
+                                (Some(w_true), Some(w_false)) => (w_true, w_false),
+                                _                             => return Bounce::Done(None),
+                                };
+    if !w_false.is_zero() {
+        mach.choices.borrow_mut().push(Choice::Flip(cont.clone(), w_false));
+    }
+    if w_true.is_zero() {
+        return backtrack(mach);
+    }
+    mach.set_weight(w_true);
+    Bounce::ApplyCont(cont, IntBool::Boolean(true))
+}
+
+// resumes the most recent choice point; failing with none left is an error
+fn backtrack(mach: &Machine) -> Bounce {
+    let choice = mach.choices.borrow_mut().pop();
+    match choice {
+        Some(Choice::Alternatives(choices, i, env, k, w)) => {
+            mach.set_weight(w);
+            try_choice(&choices, i, &env, k, mach)},
+        Some(Choice::Flip(k, w))                          => {
+            mach.set_weight(w);
+            Bounce::ApplyCont(k, IntBool::Boolean(false))},
+        Some(Choice::Collect(outcomes, k, w))             => {
+            mach.set_weight(w);
+            let vals = outcomes.borrow().iter().map(|&(ref v, _)| v.clone()).collect();
+            Bounce::ApplyCont(k, IntBool::List(Rc::new(vals)))},
+        None                                              => Bounce::Done(None),
+    }}
+
+// the template of a quote at the given nesting level. Splices at level 1 are
+// left in place, and listed in holes, in order, with the environment each is
+// evaluated in; the machine evaluates them and fill_splices puts the code
+// they produce in their places. Every variable the template binds is renamed
+// apart, in its scope and in quotes inside splices there, so that code
+// spliced in can neither capture nor be captured by the template's variables.
+// The renamings are kept in env under 'x, which no program can name
+fn quote_template(e: &Rc<LetLangExp>, level: u32, env: &LetLangEnv, mach: &Machine,
+                  holes: &mut Vec<(Rc<LetLangExp>, LetLangEnv)>) -> Result<Rc<LetLangExp>, ()> {
+    let mut quote = |e: &Rc<LetLangExp>, env: &LetLangEnv, level: u32| quote_template(e, level, env, mach, holes);
+    let exp = match **e {
+        LetLangExp::VarExp(ref s)    => LetLangExp::VarExp(renamed(s, env)),
+        LetLangExp::SetExp(ref s, ref e1) => LetLangExp::SetExp(renamed(s, env), quote(e1, env, level)?),
+        LetLangExp::LetExp(ref s, ref e1, ref e2) => {
+            let e1 = quote(e1, env, level)?;
+            let (s, body_env) = rename_apart(s, env, mach);
+            LetLangExp::LetExp(s, e1, quote(e2, &body_env, level)?)},
+        LetLangExp::ContractLetExp(ref s, ref c, ref e1, ref e2) => {
+            let e1 = quote(e1, env, level)?;
+            let c = rename_contract(c, env);
+            let (s, body_env) = rename_apart(s, env, mach);
+            LetLangExp::ContractLetExp(s, c, e1, quote(e2, &body_env, level)?)},
+        LetLangExp::NamedLetExp(ref name, ref bindings, ref body) => {
+            let mut inits = Vec::new();
+            for &(_, ref e1) in bindings.iter() {
+                inits.push(quote(e1, env, level)?);
+            }
+            let (name, mut body_env) = rename_apart(name, env, mach);
+            let mut new_bindings = Vec::new();
+            for (&(ref v, _), e1) in bindings.iter().zip(inits.into_iter()) {
+                let (v, new_env) = rename_apart(v, &body_env, mach);
+                body_env = new_env;
+                new_bindings.push((v, e1));
+            }
+            LetLangExp::NamedLetExp(name, new_bindings, quote(body, &body_env, level)?)},
+        LetLangExp::ProcExp(ref params, ref body) => {
+            let mut body_env = env.clone();
+            let mut new_params = Vec::new();
+            for &(ref v, ref t) in params.iter() {
+                let (v, new_env) = rename_apart(v, &body_env, mach);
+                body_env = new_env;
+                new_params.push((v, t.clone()));
+            }
+            LetLangExp::ProcExp(new_params, quote(body, &body_env, level)?)},
+        LetLangExp::HandleExp(ref body, ref h) => {
+            let body = quote(body, env, level)?;
+            let mut clauses = Vec::new();
+            for c in h.clauses.iter() {
+                let (param, clause_env) = rename_apart(&c.param, env, mach);
+                let (resume, clause_env) = rename_apart(&c.resume, &clause_env, mach);
+                clauses.push(OpClause { op: c.op.clone(), param: param, resume: resume,
+                                        body: quote(&c.body, &clause_env, level)? });
+            }
+            let ret = match h.ret {
+                          Some((ref x, ref e1)) => {
+                              let (x, ret_env) = rename_apart(x, env, mach);
+                              Some((x, quote(e1, &ret_env, level)?))},
+                          None                  => None,
+                          };
+            LetLangExp::HandleExp(body, Rc::new(Handler { clauses: clauses, ret: ret }))},
+        LetLangExp::QuoteExp(ref e1) => LetLangExp::QuoteExp(quote(e1, env, level + 1)?),
+        LetLangExp::SpliceExp(ref e1) if level == 1 => {
+            holes.push((e1.clone(), env.clone()));
+            return Ok(e.clone())},
+        LetLangExp::SpliceExp(ref e1) => LetLangExp::SpliceExp(quote(e1, env, level - 1)?),
+        _ => e.map_children(|e1| quote(e1, env, level))?,
+    };
+    Ok(Rc::new(exp))
+}
+
+// evaluates the splices of template from the i-th hole on, where i is the
+// count of vals, the code those before it produced; then fills them in
+fn next_splice(template: Rc<LetLangExp>, holes: Rc<Vec<(Rc<LetLangExp>, LetLangEnv)>>, vals: Vec<IntBool>,
+               cont: Rc<Cont>) -> Bounce {
+    if vals.len() < holes.len() {
+        let (e, env) = holes[vals.len()].clone();
+        return Bounce::ValueOf(e, env, Rc::new(Cont::QuoteCont(template, holes, vals, cont)));
+    }
+    match fill_splices(&template, 1, &mut vals.into_iter()) {
+        Ok(code) => Bounce::ApplyCont(cont, IntBool::Code(code)),
+        Err(())  => Bounce::Done(None),  // a splice of something other than code
+    }}
+
+// the template with its level 1 splices replaced, in order, by the code in vals
+fn fill_splices(e: &Rc<LetLangExp>, level: u32, vals: &mut vec::IntoIter<IntBool>) -> Result<Rc<LetLangExp>, ()> {
+    let exp = match **e {
+        LetLangExp::SpliceExp(_) if level == 1 => return match vals.next() {
+                                                      Some(IntBool::Code(code)) => Ok(code),
+                                                      _                         => Err(()),
+                                                  },
+        LetLangExp::SpliceExp(ref e1) => LetLangExp::SpliceExp(fill_splices(e1, level - 1, vals)?),
+        LetLangExp::QuoteExp(ref e1)  => LetLangExp::QuoteExp(fill_splices(e1, level + 1, vals)?),
+        _                             => e.map_children(|e1| fill_splices(e1, level, vals))?,
+    };
+    Ok(Rc::new(exp))
+}
+
+fn renamed(s: &String, env: &LetLangEnv) -> String {
+    match env.apply_env(&format!("'{}", s)) {
+        Some(IntBool::Str(new_name)) => new_name,
+        _                            => s.clone(),
+    }}
+
+// predicates in scope are variables like any other
+fn rename_contract(c: &Rc<Contract>, env: &LetLangEnv) -> Rc<Contract> {
+    Rc::new(match **c {
+        Contract::Flat(ref pred)           => Contract::Flat(renamed(pred, env)),
+        Contract::Arrow(ref args, ref res) =>
+            Contract::Arrow(args.iter().map(|a| rename_contract(a, env)).collect(), rename_contract(res, env)),
+    })
+}
+
+fn rename_apart(s: &String, env: &LetLangEnv, mach: &Machine) -> (String, LetLangEnv) {
+    let new_name = mach.fresh_name(s);
+    let new_env = env.extend_env(&format!("'{}", s), IntBool::Str(new_name.clone()));
+    (new_name, new_env)
+}
+
+// the body sees the host class's fields, then self and %super, then the arguments
+fn apply_method(method: &Method, obj: &Rc<Object>, args: &[IntBool], caller_env: &LetLangEnv, cont: Rc<Cont>,
+                mach: &Machine) -> Bounce {
+    if method.params.len() != args.len() {
+        return Bounce::Done(None);
+    }
+    let mut new_env = match mach.scoping {
+                          Scoping::Lexical => method.env.clone(),
+                          Scoping::Dynamic => caller_env.clone(),
+                          };
+    for (f, cell) in method.field_names.iter().zip(obj.fields.iter()) {
+        new_env = new_env.extend_env_ref(f, cell);
+    }
+    new_env = new_env.extend_env(&("self".to_string()), IntBool::Object(obj.clone()))
+                     .extend_env(&("%super".to_string()), IntBool::Str(method.super_name.clone()));
+    for (param, arg) in method.params.iter().zip(args.iter()) {
+        new_env = new_env.extend_env(param, arg.clone());
+    }
+    Bounce::ValueOf(method.body.clone(), new_env, cont)
+}
+
+// vals alternate key, value; a later duplicate key overrides an earlier one
+fn value_of_dict(vals: &Vec<IntBool>) -> Option<IntBool> {
+    let mut map = BTreeMap::new();
+    for pair in vals.chunks(2) {
+        let key = DictKey::from_val(&pair[0])?;  // only int, bool and string keys
+        map.insert(key, pair[1].clone());
+    }
+    Some(IntBool::Dict(Rc::new(map)))
+}
+
+// applies op to the map once the dictionary and key operands are checked
+fn dict_prim<F>(dict_val: &IntBool, key_val: &IntBool, op: F) -> Option<IntBool>
+    where F: FnOnce(&BTreeMap<DictKey, IntBool>, DictKey) -> Option<IntBool> {
+    let key = DictKey::from_val(key_val)?;
+    match *dict_val {
+        IntBool::Dict(ref map) => op(map, key),
+        _                      => None,
+    }}
+
+// None if the difference is out of the range of i32
+fn value_of_diff_exp_work(a1: &IntBool, a2: &IntBool) -> Option<i32> {
+    let a1_int_val: i32 = match *a1 {
+                            IntBool::Integer(i) => i,
+                            _                   => 0,
+                            };
+    let a2_int_val: i32 = match *a2 {
+                            IntBool::Integer(i) => i,
+                            _                   => 0,
+                            };
+    a1_int_val.checked_sub(a2_int_val)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use let_lang_scanner::*;
+    use let_lang_parser::*;
+
+    fn run(src: &str) -> Option<IntBool> {
+        let pgm = parse(&tokenize(src).unwrap()).unwrap();
+        value_of_program(&pgm, &LetLangEnv::new_env())
+    }
+
+    // the value, or the message of the error that stopped the program
+    fn eval(src: &str) -> Result<IntBool, String> {
+        let pgm = parse(&tokenize(src).unwrap()).unwrap();
+        eval_program(&pgm, &LetLangEnv::new_env()).map_err(|e| format!("{}", e))
+    }
+
+    // the distribution as "value: weight" pairs
+    fn dist(src: &str) -> Option<Vec<String>> {
+        let pgm = parse(&tokenize(src).unwrap()).unwrap();
+        distribution_of_program(&pgm, &LetLangEnv::new_env())
+            .map(|d| d.iter().map(|&(ref v, ref w)| format!("{}: {}", v.to_string(), w)).collect())
+    }
+
+    #[test]
+    fn defines_are_seen_by_the_ones_after_them() {
+        assert_eq!(run("define a = 1 define b = -(a, -1) define c = -(b, -1) -(c, a)"),
+                   Some(IntBool::Integer(2)));
+        assert_eq!(run("define a = 1 define b = -(a, -1)"), Some(IntBool::Integer(2)));
+        assert_eq!(run("define x = 1 define y = x define x = 2 -(x, y)"),
+                   Some(IntBool::Integer(1)));
+        assert_eq!(run("define a = b define b = 1 a"), None);
+    }
+
+    #[test]
+    fn named_let_runs_in_constant_stack() {
+        assert_eq!(run("let loop (i = 0, acc = 0)
+                        in if iszero(-(i, 100000)) then acc else loop(-(i, -1), -(acc, -2))"),
+                   Some(IntBool::Integer(200000)));
+    }
+
+    #[test]
+    fn while_with_assignable_variables() {
+        assert_eq!(run("let i = 0 in let total = 0
+                        in begin
+                             while if iszero(-(i, 50000)) then false else true
+                             do begin set total = -(total, -3); set i = -(i, -1) end;
+                             total
+                           end"),
+                   Some(IntBool::Integer(150000)));
+    }
+
+    #[test]
+    fn classes_dispatch_and_super() {
+        let classes = "class point extends object
+                         field x
+                         field y
+                         method initialize(ix, iy) begin set x = ix; set y = iy end
+                         method move(dx) begin set x = -(x, dx); self end
+                         method sum() -(x, -(0, y))
+                       class colorpoint extends point
+                         field x
+                         method initialize(ix, iy) begin super initialize(ix, iy); set x = 100 end
+                         method sum() -(super sum(), x)
+                         method color() x";
+        assert_eq!(run(&format!("{} let p = new colorpoint(7, 3) in send send p move(2) sum()", classes)),
+                   Some(IntBool::Integer(-92)));
+        assert_eq!(run(&format!("{} send new point(1, 2) color()", classes)), None);
+    }
+
+    #[test]
+    fn instanceof_and_cast_follow_superclasses_and_interfaces() {
+        let classes = "interface shape method int area()
+                       class square extends object implements shape
+                         field int side
+                         method void initialize(s : int) set side = s
+                         method int area() side
+                       class cube extends square
+                         method int volume() 8";
+        assert_eq!(run(&format!("{} instanceof new cube(2) shape", classes)), Some(IntBool::Boolean(true)));
+        assert_eq!(run(&format!("{} instanceof new square(2) cube", classes)), Some(IntBool::Boolean(false)));
+        assert_eq!(run(&format!("{} send cast new cube(2) square area()", classes)), Some(IntBool::Integer(2)));
+        assert_eq!(run(&format!("{} cast new square(2) cube", classes)), None);
+    }
+
+    #[test]
+    fn loop_called_outside_tail_position() {
+        assert_eq!(run("let count (n = 100000) in if iszero(n) then 0 else -(count(-(n, 1)), -1)"),
+                   Some(IntBool::Integer(100000)));
+        assert_eq!(run("let loop (n = 1) in loop(n, n)"), None);
+    }
+
+    #[test]
+    fn handlers_abort_or_resume() {
+        // an exception: the handler never resumes
+        assert_eq!(run("handle -(1, perform raise(5)) with {raise(v, k) -> -(v, 100)}"),
+                   Some(IntBool::Integer(-95)));
+        // deep: the second perform runs inside the resumed frames and is handled too
+        assert_eq!(run("handle -(perform one(0), -(0, perform one(0))) with {one(u, k) -> k(1)}"),
+                   Some(IntBool::Integer(2)));
+        // multi-shot: k is resumed twice
+        assert_eq!(run("handle -(perform choose(0), 1) with {choose(u, k) -> -(k(10), k(20))}"),
+                   Some(IntBool::Integer(-10)));
+        // an operation the inner handler lacks goes to the outer one
+        assert_eq!(run("handle handle -(perform ask(0), 1) with {tell(v, k) -> 0} with {ask(u, k) -> k(42)}"),
+                   Some(IntBool::Integer(41)));
+        assert_eq!(run("perform oops(1)"), None);
+    }
+
+    #[test]
+    fn state_as_a_handler() {
+        let counter = "let f = handle let x = perform get(0)
+                                      in begin perform put(-(x, -5)); perform get(0) end
+                               with {get(u, k) -> proc (s) let g = k(s) in g(s),
+                                     put(v, k) -> proc (s) let g = k(v) in g(v),
+                                     return(x) -> proc (s) x}
+                       in f(10)";
+        assert_eq!(run(counter), Some(IntBool::Integer(15)));
+    }
+
+    #[test]
+    fn generators_suspend_at_yield() {
+        let naturals = "let g = generator let n = 0 in while true do begin yield n; set n = -(n, -1) end
+                        in begin next(g); next(g); next(g) end";
+        assert_eq!(run(naturals), Some(IntBool::Integer(2)));
+        let sum = "let g = generator begin yield 1; yield 2; yield 3 end
+                   in let sum = 0
+                      in begin
+                           while if done?(g) then false else true do set sum = -(sum, -(0, next(g)));
+                           sum
+                         end";
+        assert_eq!(run(sum), Some(IntBool::Integer(6)));
+        // each generator keeps its own state; done? does not consume a value
+        let two = "let a = generator begin yield 10; yield 20 end
+                   in let b = generator begin yield 1; yield 2 end
+                      in begin next(a); done?(b); done?(b); -(next(a), next(b)) end";
+        assert_eq!(run(two), Some(IntBool::Integer(19)));
+    }
+
+    #[test]
+    fn exhausted_generators_and_stray_yields() {
+        assert_eq!(run("let g = generator yield 1 in begin next(g); done?(g) end"), Some(IntBool::Boolean(true)));
+        assert_eq!(run("let g = generator yield 1 in begin next(g); next(g) end"), None);
+        assert_eq!(run("yield 1"), None);
+    }
+
+    #[test]
+    fn amb_backtracks_on_failed_requirements() {
+        assert_eq!(run("let x = amb(1, 2, 3) in begin require(iszero(-(x, 2))); x end"),
+                   Some(IntBool::Integer(2)));
+        // a later failure backtracks into the most recent choice first
+        let pairs = "collect-all let a = amb(1, 2, 3, 4)
+                                 in let b = amb(1, 2, 3, 4)
+                                    in begin require(iszero(-(-(a, b), 2))); {a: b} end";
+        assert_eq!(run(pairs).unwrap().to_string(), "[{3: 1}, {4: 2}]");
+        assert_eq!(run("collect-all let x = amb(1, 2) in begin require(false); x end"),
+                   Some(IntBool::List(Rc::new(Vec::new()))));
+        assert_eq!(run("require(false)"), None);
+        assert_eq!(run("amb()"), None);
+    }
+
+    #[test]
+    fn collect_all_nests() {
+        let nested = "collect-all let x = amb(1, 2) in collect-all -(x, amb(0, 10))";
+        assert_eq!(run(nested).unwrap().to_string(), "[[1, -9], [2, -8]]");
+    }
+
+    #[test]
+    fn flips_give_exact_distributions() {
+        assert_eq!(dist("flip(30)").unwrap(), vec!["true: 3/10", "false: 7/10"]);
+        let sum = "let a = if flip(50) then 1 else 0
+                   in let b = if flip(50) then 1 else 0
+                      in -(a, -(0, b))";
+        assert_eq!(dist(sum).unwrap(), vec!["2: 1/4", "1: 1/2", "0: 1/4"]);
+        assert_eq!(dist("if flip(0) then 1 else 2").unwrap(), vec!["2: 1/1"]);
+        assert_eq!(dist("flip(150)"), None);
+        assert_eq!(eval("if true then\n  flip(150) else false"),
+                   Err("EvalError: flip takes a percent from 0 to 100, not 150 (line: 2, column: 3)".to_string()));
+        assert_eq!(eval("flip(true)"),
+                   Err("EvalError: flip takes a percent from 0 to 100, not true (line: 1, column: 1)".to_string()));
+    }
+
+    #[test]
+    fn observations_condition_the_distribution() {
+        let first_coin = "let a = flip(50)
+                          in let b = flip(50)
+                             in begin observe(if a then true else b); a end";
+        assert_eq!(dist(first_coin).unwrap(), vec!["true: 2/3", "false: 1/3"]);
+        assert_eq!(dist("let a = flip(50) in begin observe(false); a end"), None);
+    }
+
+    #[test]
+    fn splices_build_code_that_run_evaluates() {
+        assert_eq!(run("run quote -(5, 2)"), Some(IntBool::Integer(3)));
+        let specialize = "let sub = proc (n, c)
+                                     let build (i = n, acc = c)
+                                     in if iszero(i) then acc
+                                        else build(-(i, 1), quote -(splice acc, 1))
+                          in let code = quote proc (x) splice sub(3, quote x)
+                             in let f = run code
+                                in f(10)";
+        assert_eq!(run(specialize), Some(IntBool::Integer(7)));
+        assert_eq!(run("splice quote 1"), None);
+        assert_eq!(run("run 5"), None);
+        assert_eq!(run("run quote splice 5"), None);
+    }
+
+    #[test]
+    fn differences_stay_in_the_i32_range() {
+        assert_eq!(eval("-(-2147483647, 1)"), Ok(IntBool::Integer(i32::min_value())));
+        assert_eq!(eval("-(-2147483648, 1)"),
+                   Err("EvalError: integer overflow in -(-2147483648, 1) (line: 1, column: 1)".to_string()));
+        assert_eq!(eval("let x = -2147483648 in\n  -(0, x)"),
+                   Err("EvalError: integer overflow in -(0, -2147483648) (line: 2, column: 3)".to_string()));
+    }
+
+    #[test]
+    fn splices_run_on_the_machine() {
+        let backtrack = "let x = amb(1, 2)
+                         in -(run quote -(splice begin require(iszero(-(x, 2))); quote 10 end, 1), x)";
+        assert_eq!(run(backtrack), Some(IntBool::Integer(7)));
+        let resumed = "handle run quote splice begin perform ask(0); quote 1 end with {ask(u, k) -> k(0)}";
+        assert_eq!(run(resumed), Some(IntBool::Integer(1)));
+        let deep = "let build (i = 3000) in if iszero(i) then quote 0 else quote -(splice build(-(i, 1)), -1)";
+        assert_eq!(run(&format!("run {}", deep)), Some(IntBool::Integer(3000)));
+    }
+
+    #[test]
+    fn quoted_binders_do_not_capture() {
+        let hygiene = "let f = proc (c) quote let x = 1 in -(splice c, x)
+                       in run quote let x = 100 in splice f(quote x)";
+        assert_eq!(run(hygiene), Some(IntBool::Integer(99)));
+        assert_eq!(run("let x = 5 in run quote x"), None);
+        let printed = run("quote let x = 1 in x").unwrap().to_string();
+        assert_eq!(printed, "quote let x#1 = 1 in x#1");
+        assert!(tokenize(&printed).is_err());
+    }
+
+    #[test]
+    fn dynamic_scoping_uses_the_callers_bindings() {
+        let src = "let x = 1 in let f = proc (y) -(y, x) in let x = 10 in f(20)";
+        let pgm = parse(&tokenize(src).unwrap()).unwrap();
+        let env = LetLangEnv::new_env();
+        assert_eq!(value_of_program_scoped(&pgm, &env, Scoping::Lexical), Some(IntBool::Integer(19)));
+        assert_eq!(value_of_program_scoped(&pgm, &env, Scoping::Dynamic), Some(IntBool::Integer(10)));
+        let looped = "let loop (i = 3) in if iszero(i) then 0 else loop(-(i, 1))";
+        let pgm = parse(&tokenize(looped).unwrap()).unwrap();
+        assert_eq!(value_of_program_scoped(&pgm, &env, Scoping::Dynamic), Some(IntBool::Integer(0)));
+        assert!(scoping_diffs(&pgm, &env).is_empty());
+        let escaped = "let f = let z = 1 in proc (y) z in f(2)";
+        let pgm = parse(&tokenize(escaped).unwrap()).unwrap();
+        assert_eq!(value_of_program_scoped(&pgm, &env, Scoping::Dynamic), None);
+    }
+
+    #[test]
+    fn scoping_diffs_list_what_diverges() {
+        let src = "define x = 1
+                   define f = proc (y) -(y, x)
+                   define a = let x = 100 in f(0)
+                   define b = f(5)
+                   define g = let x = 2 in proc () x
+                   let x = 7 in f(x)";
+        let pgm = parse(&tokenize(src).unwrap()).unwrap();
+        let diffs: Vec<String> = scoping_diffs(&pgm, &LetLangEnv::new_env()).iter().map(|d| format!("{}", d)).collect();
+        assert_eq!(diffs, vec!["a: lexical -1, dynamic -100", "main expression: lexical 6, dynamic 0"]);
+        let broken = "define g = let z = 1 in proc () z
+                      define c = g()
+                      3";
+        let pgm = parse(&tokenize(broken).unwrap()).unwrap();
+        let diffs: Vec<String> = scoping_diffs(&pgm, &LetLangEnv::new_env()).iter().map(|d| format!("{}", d)).collect();
+        assert_eq!(diffs, vec!["c: lexical 1, dynamic error", "main expression: lexical 3, dynamic error"]);
+    }
+
+    #[test]
+    fn assertions_and_flat_contracts() {
+        assert_eq!(eval("let x = 3 in begin assert(iszero(-(x, 3)), \"x is three\"); x end"), Ok(IntBool::Integer(3)));
+        assert_eq!(eval("let x = 4 in assert(iszero(x), \"x is zero\")"),
+                   Err("EvalError: assertion failed: x is zero; blaming assert(iszero(x), \"x is zero\") \
+                        (line: 1, column: 14)".to_string()));
+        assert_eq!(eval("let x : positive? = 5 in x"), Ok(IntBool::Integer(5)));
+        assert_eq!(eval("let x : positive? = -(2, 5) in x"),
+                   Err("EvalError: contract violation: -3 does not satisfy positive? in let x : positive?; \
+                        blaming the expression bound to x (line: 1, column: 1)".to_string()));
+        assert_eq!(eval("let small = proc (n) iszero(-(n, 1)) in let x : small = 2 in x"),
+                   Err("EvalError: contract violation: 2 does not satisfy small in let x : small; \
+                        blaming the expression bound to x (line: 1, column: 41)".to_string()));
+        assert_eq!(eval("let x : prime? = 5 in x"),
+                   Err("EvalError: unknown contract prime? in let x : prime? (line: 1, column: 1)".to_string()));
+        assert_eq!(eval("-(1, nothing)").unwrap_err(), "EvalError: runtime error (line: 1, column: 6)");
+    }
+
+    #[test]
+    fn arrow_contracts_blame_the_caller_or_the_procedure() {
+        let ok = "let f : (positive? -> positive?) = proc (n) -(n, 1) in f(5)";
+        assert_eq!(eval(ok), Ok(IntBool::Integer(4)));
+        let bad_arg = "let f : (positive? -> positive?) = proc (n) -(n, 1) in f(-(0, 5))";
+        assert_eq!(eval(bad_arg),
+                   Err("EvalError: contract violation: -5 does not satisfy positive? in \
+                        let f : (positive? -> positive?); blaming the body of let f (line: 1, column: 1)".to_string()));
+        let bad_result = "let f : (positive? -> positive?) = proc (n) -(n, 1) in f(1)";
+        assert!(eval(bad_result).unwrap_err().contains("blaming the expression bound to f (line: 1, column: 1)"));
+        let arity = "let f : (int? -> int?) = proc (n) n in f(1, 2)";
+        assert!(eval(arity).unwrap_err().contains("2 arguments given to a procedure of 1"));
+        // the argument is itself a procedure, so blame flips twice for its own arguments
+        let higher = "let apply : ((positive? -> int?) -> int?) = proc (g) g(0)
+                      in apply(proc (n) n)";
+        assert!(eval(higher).unwrap_err().contains("blaming the expression bound to apply"));
+        assert!(eval("let f : (int? -> int?) = 3 in f").unwrap_err().contains("3 does not satisfy (int? -> int?)"));
+    }
+}
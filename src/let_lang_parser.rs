@@ -3,207 +3,1208 @@
 
 use let_lang_scanner::*;
 use let_lang_exp::*;      // needed for building ASTs
+use let_lang_type::*;
 
+use std::collections::VecDeque;
 use std::fmt;
-use std::slice;
+use std::io::Read;
+use std::iter;
+use std::rc::Rc;
 
-pub fn parse(tokens: &Vec<Token>) -> Result<LetLangExp, ParseErr> {
-    Parser::parse(tokens)
+// every syntax error in the tokens, in order, if there are any
+pub fn parse(tokens: &Vec<Token>) -> Result<Program, Vec<ParseErr>> {
+    let (pgm, errors) = Parser::parse(tokens.iter().cloned());
+    if errors.is_empty() { Ok(pgm) } else { Err(errors) }
 }
 
+// the program, with an ErrorExp wherever the parser skipped over a syntax
+// error, and the errors themselves
+pub fn parse_recovering(tokens: &Vec<Token>) -> (Program, Vec<ParseErr>) {
+    Parser::parse(tokens.iter().cloned())
+}
+
+// parses the program a reader holds, scanning its tokens only as the parser
+// comes to them; the first lexical error ends the input, and is what is
+// reported
+pub fn parse_reader<R: Read>(reader: R) -> Result<Program, SyntaxErr> {
+    let mut lexer = Lexer::new(reader);
+    let mut lex_err = None;
+    let (pgm, errors) = {
+        let tokens = iter::from_fn(|| match lexer.next() {
+                                          Some(Ok(token)) => Some(token),
+                                          Some(Err(err))  => { lex_err = Some(err);
+                                                               None },
+                                          None            => None,
+                                      });
+        Parser::parse(tokens)
+    };
+    match lex_err {
+        Some(err)                   => Err(SyntaxErr::Lex(err)),
+        None if errors.is_empty()   => Ok(pgm),
+        None                        => Err(SyntaxErr::Parse(errors)),
+    }
+}
+
+// why a program read by parse_reader could not be parsed
+pub enum SyntaxErr {
+    Lex(LexErr),
+    Parse(Vec<ParseErr>),
+}
+
+impl fmt::Display for SyntaxErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SyntaxErr::Lex(ref err)    => write!(f, "{}", err),
+            SyntaxErr::Parse(ref errs) => {
+                let lines: Vec<String> = errs.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            },
+        }
+    }}
+impl fmt::Debug for SyntaxErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }}
+
+// expected lists what would have been valid where the error was found, and
+// span is that of the offending token
+#[derive(Clone)]
 pub struct ParseErr {
-    message: String,
+    pub message: String,
+    pub expected: Vec<String>,
+    pub span: Span,
+}
+
+impl ParseErr {
+    // the error followed by the line of src it points at, underlined
+    pub fn render(&self, src: &str) -> String {
+        format!("{}\n{}", self, self.span.excerpt(src))
+    }
 }
 
 impl fmt::Display for ParseErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ParseError: {}", self.message)
+        write!(f, "ParseError: {} ({})", self.message, self.span)
     }}
 impl fmt::Debug for ParseErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ParseError: {}", self.message)
+        write!(f, "{}", self)
     }}
 
 macro_rules! parse_err {
-    ($($arg:tt)*) => (
-        return Err(ParseErr { message: format!($($arg)*)})
+    ($parser:ident, $span:expr, $($arg:tt)*) => (
+        return Err($parser.error_at($span, format!($($arg)*)))
     )
 }
 
-// Parser datatype is struct with one field.
-#[derive(Clone)]
-struct Parser<'a> {
-    tokens: slice::Iter<'a, Token>,
+// Parser datatype is struct with the source of the tokens still to be
+// parsed, the next one or two of them, the count of those consumed, how
+// many brackets they leave open, the span of the last one and where the
+// input ends after it, what the parser has looked for and not found at
+// position expected_pos, and the errors recovered from so far
+struct Parser<'src, I> {
+    tokens: I,
+    lookahead: VecDeque<Token<'src>>,
+    consumed: usize,
+    depth: i32,
+    last: Span,
+    end: Span,
+    expected: Vec<String>,
+    expected_pos: usize,
+    errors: Vec<ParseErr>,
 }
 
-impl<'a> Parser<'a> {
+// +1 for a token that opens a bracketed construct, -1 for one that closes
+// it; let ... in and if ... else count as brackets
+fn nesting(kind: &TokenKind) -> i32 {
+    match *kind {
+        TokenKind::Lparen | TokenKind::Lbrace | TokenKind::Lbracket | TokenKind::Begin
+        | TokenKind::Let | TokenKind::If                                               => 1,
+        TokenKind::Rparen | TokenKind::Rbrace | TokenKind::Rbracket | TokenKind::End
+        | TokenKind::In | TokenKind::Else                                              => -1,
+        _                                                                              => 0,
+    }
+}
+
+impl<'src, I: Iterator<Item = Token<'src>>> Parser<'src, I> {
     // This is a constructor for a Parser object.
-    // Takes a reference to a token vector as input.
-    // Builds Parser and then calls parse_let_lang_exp().
+    // Takes the tokens, which are pulled as they are needed.
+    // Builds Parser and then calls parse_program().
     // "parser" must be mutable b/c the tokens field is updated.
-    fn parse(tokens: &Vec<Token>) -> Result<LetLangExp, ParseErr> {
-        let mut parser = Parser { tokens: tokens.iter() };
-        let ast_root = parser.parse_let_lang_exp();
-        let option_next_tok = parser.tokens.next();
-        match option_next_tok {
-            Some(tok) => parse_err!("Toplevel: Extra input at end of parse: {:?}", tok),
-            _          => ast_root,
+    fn parse(tokens: I) -> (Program, Vec<ParseErr>) {
+        let nowhere = Span::at(0, 1, 1, 1);
+        let mut parser = Parser { tokens: tokens, lookahead: VecDeque::new(), consumed: 0, depth: 0,
+                                  last: nowhere, end: nowhere,
+                                  expected: Vec::new(), expected_pos: 0, errors: Vec::new() };
+        parser.fill(1);
+        let ast_root = parser.parse_program();
+        if parser.peek().is_some() {
+            parser.expect("end of input".to_string());
+            let err = parser.unexpected();
+            parser.report(err);
+        }
+        (ast_root, parser.errors)
+    }
+    // program ::= {definition}* [expression]
+    // definition ::= define identifier = expression | module_defn | class_decl | interface_decl
+    // a definition with a syntax error is skipped up to the start of the next
+    fn parse_program(&mut self) -> Program {
+        let mut defs: Vec<Definition> = Vec::new();
+        loop {
+            let defn = match self.peek() {
+                Some(&TokenKind::Define)    => self.parse_val_defn(),
+                Some(&TokenKind::Module)    => self.parse_module_defn(),
+                Some(&TokenKind::Class)     => self.parse_class_decl(),
+                Some(&TokenKind::Interface) => self.parse_interface_decl(),
+                _                       => {
+                    for kw in &[TokenKind::Define, TokenKind::Module, TokenKind::Class, TokenKind::Interface] {
+                        self.expect(kw.describe());
+                    }
+                    break
+                },
+            };
+            match defn {
+                Ok(d)    => defs.push(d),
+                Err(err) => {
+                    self.report(err);
+                    self.advance();
+                    while self.peek().map_or(false, |tok| match *tok {
+                              TokenKind::Define | TokenKind::Module | TokenKind::Class | TokenKind::Interface => false,
+                              _                                                                          => true,
+                          }) {
+                        self.advance();
+                    }
+                },
+            }
         }
+        let body = match self.peek() {
+            None if !defs.is_empty() || !self.errors.is_empty() => None,   // definitions only
+            _                                                    => self.parse_let_lang_exp().ok(),
+        };
+        Program::new_program(&defs, body.as_ref())
+    }
+    // define identifier = expression
+    fn parse_val_defn(&mut self) -> Result<Definition, ParseErr> {
+        self.match_token(&TokenKind::Define)?;
+        let s = self.get_string()?;           // match defined name
+        self.match_token(&TokenKind::Assign)?;    // match "="
+        let e = self.parse_let_lang_exp()?;
+        Ok(Definition::new_val_defn(&s, &e))
+    }
+    // module m interface [decl ...] body [defn ...]
+    // decl ::= opaque t | transparent t = type | x : type
+    // defn ::= type t = type | x = exp
+    fn parse_module_defn(&mut self) -> Result<Definition, ParseErr> {
+        self.match_token(&TokenKind::Module)?;
+        let name = self.get_string()?;
+        self.match_token(&TokenKind::Interface)?;
+        self.match_token(&TokenKind::Lbracket)?;
+        let mut iface_types: Vec<(String, Option<Type>)> = Vec::new();
+        let mut interface: Vec<(String, Type)> = Vec::new();
+        loop {
+            match self.peek() {
+                Some(&TokenKind::Rbracket)    => break,
+                Some(&TokenKind::Opaque)      => {
+                    self.match_token(&TokenKind::Opaque)?;
+                    iface_types.push((self.get_string()?, None));
+                },
+                Some(&TokenKind::Transparent) => {
+                    self.match_token(&TokenKind::Transparent)?;
+                    let t = self.get_string()?;
+                    self.match_token(&TokenKind::Assign)?;
+                    iface_types.push((t, Some(self.parse_type()?)));
+                },
+                _                         => {
+                    for kw in &[TokenKind::Rbracket, TokenKind::Opaque, TokenKind::Transparent] {
+                        self.expect(kw.describe());
+                    }
+                    let s = self.get_string()?;
+                    self.match_token(&TokenKind::Colon)?;
+                    interface.push((s, self.parse_type()?));
+                },
+            }
+        }
+        self.match_token(&TokenKind::Rbracket)?;
+        self.match_token(&TokenKind::Body)?;
+        self.match_token(&TokenKind::Lbracket)?;
+        let mut body_types: Vec<(String, Type)> = Vec::new();
+        let mut body: Vec<(String, LetLangExp)> = Vec::new();
+        loop {
+            match self.peek() {
+                Some(&TokenKind::Rbracket) => break,
+                Some(&TokenKind::TypeKw)   => {
+                    self.match_token(&TokenKind::TypeKw)?;
+                    let t = self.get_string()?;
+                    self.match_token(&TokenKind::Assign)?;
+                    body_types.push((t, self.parse_type()?));
+                },
+                _                      => {
+                    for kw in &[TokenKind::Rbracket, TokenKind::TypeKw] {
+                        self.expect(kw.describe());
+                    }
+                    let s = self.get_string()?;
+                    self.match_token(&TokenKind::Assign)?;
+                    body.push((s, self.parse_let_lang_exp()?));
+                },
+            }
+        }
+        self.match_token(&TokenKind::Rbracket)?;
+        Ok(Definition::new_module_defn(&name, &iface_types, &interface, &body_types, &body))
+    }
+    // class c extends d {implements i}* {field [type] x}* {method_sig exp}*
+    fn parse_class_decl(&mut self) -> Result<Definition, ParseErr> {
+        self.match_token(&TokenKind::Class)?;
+        let name = self.get_string()?;
+        self.match_token(&TokenKind::Extends)?;
+        let super_name = self.get_string()?;
+        let mut interfaces = Vec::new();
+        while self.at(&TokenKind::Implements) {
+            self.match_token(&TokenKind::Implements)?;
+            interfaces.push(self.get_string()?);
+        }
+        let mut fields = Vec::new();
+        while self.at(&TokenKind::Field) {
+            self.match_token(&TokenKind::Field)?;
+            let t = self.parse_leading_type()?;
+            fields.push((self.get_string()?, t));
+        }
+        let mut methods = Vec::new();
+        while self.at(&TokenKind::Method) {
+            let sig = self.parse_method_sig()?;
+            let body = self.parse_let_lang_exp()?;
+            methods.push(MethodDecl { sig: sig, body: Rc::new(body) });
+        }
+        Ok(Definition::new_class_defn(&ClassDecl { name: name, super_name: super_name, interfaces: interfaces,
+                                                   fields: fields, methods: methods }))
     }
+    // interface i {method_sig}*
+    fn parse_interface_decl(&mut self) -> Result<Definition, ParseErr> {
+        self.match_token(&TokenKind::Interface)?;
+        let name = self.get_string()?;
+        let mut methods = Vec::new();
+        while self.at(&TokenKind::Method) {
+            methods.push(self.parse_method_sig()?);
+        }
+        Ok(Definition::new_interface_defn(&InterfaceDecl { name: name, methods: methods }))
+    }
+    // method_sig ::= method [type] m(x [: type], ...)
+    fn parse_method_sig(&mut self) -> Result<MethodSig, ParseErr> {
+        self.match_token(&TokenKind::Method)?;
+        let result = self.parse_leading_type()?;
+        let name = self.get_string()?;
+        let params = self.parse_params()?;
+        Ok(MethodSig { name: name, params: params, result: result })
+    }
+    // the optional type in front of a field or method name: present if the
+    // name is preceded by another identifier, or the type is compound
+    fn parse_leading_type(&mut self) -> Result<Option<Type>, ParseErr> {
+        self.fill(2);
+        match (self.lookahead.get(0).map(|t| &t.kind), self.lookahead.get(1).map(|t| &t.kind)) {
+            (Some(&TokenKind::Identifier(_)), Some(&TokenKind::Identifier(_))) |
+            (Some(&TokenKind::Lparen), _) | (Some(&TokenKind::From), _) => Ok(Some(self.parse_type()?)),
+            _                                                    => Ok(None),
+        }
+    }
+    // (x [: type], ...), possibly empty. A list with a syntax error is
+    // skipped up to its closing bracket, keeping the parameters before the
+    // error, so that whatever the list belongs to goes on to its body
+    fn parse_params(&mut self) -> Result<Vec<(String, Option<Type>)>, ParseErr> {
+        let start_depth = self.depth;
+        self.match_token(&TokenKind::Lparen)?;
+        let mut params: Vec<(String, Option<Type>)> = Vec::new();
+        if let Err(err) = self.parse_params_work(&mut params) {
+            self.report(err.clone());
+            self.recover(start_depth)?;
+            if self.depth > start_depth {
+                return Err(err);
+            }
+        }
+        Ok(params)
+    }
+    fn parse_params_work(&mut self, params: &mut Vec<(String, Option<Type>)>) -> Result<(), ParseErr> {
+        if !self.at(&TokenKind::Rparen) {
+            loop {
+                let v = self.get_string()?;
+                let t = if self.at(&TokenKind::Colon) {
+                            self.match_token(&TokenKind::Colon)?;
+                            Some(self.parse_type()?)
+                        } else {
+                            None
+                        };
+                params.push((v, t));
+                if !self.at(&TokenKind::Comma) { break; }
+                self.match_token(&TokenKind::Comma)?;
+            }
+        }
+        self.match_token(&TokenKind::Rparen)?;
+        Ok(())
+    }
+    // type ::= int | bool | string | list | dict | any | t | from m take t
+    //        | (type, ..., type -> type)
+    fn parse_type(&mut self) -> Result<Type, ParseErr> {
+        match self.peek() {
+            Some(&TokenKind::Identifier(_)) => Ok(Type::from_ident(&self.take_text())),
+            Some(&TokenKind::From)              => {
+                self.advance();
+                let m = self.get_string()?;
+                self.match_token(&TokenKind::Take)?;
+                let t = self.get_string()?;
+                Ok(Type::QualifiedType(m, t))
+            },
+            Some(&TokenKind::Lparen)            => {
+                self.advance();
+                let mut args = Vec::new();
+                if !self.at(&TokenKind::Arrow) {
+                    loop {
+                        args.push(self.parse_type()?);
+                        if !self.at(&TokenKind::Comma) { break; }
+                        self.match_token(&TokenKind::Comma)?;
+                    }
+                }
+                self.match_token(&TokenKind::Arrow)?;
+                let result = self.parse_type()?;
+                self.match_token(&TokenKind::Rparen)?;
+                Ok(Type::new_proc_type(&args, &result))
+            },
+            _                               => {
+                self.expect("a type".to_string());
+                Err(self.unexpected())
+            },
+        }
+    }
+    // every expression parsed is wrapped in the span of its tokens; one with
+    // a syntax error is reported, skipped and parsed as an ErrorExp, so this
+    // always succeeds
     fn parse_let_lang_exp(&mut self) -> Result<LetLangExp, ParseErr> {
-        let option_peek: Option<&Token> = self.tokens.clone().next();
-        match option_peek {
-                Some(peek_token) => self.parse_lle_work(peek_token.clone()),
-                None             => parse_err!("Unexpected end of input")
+        let start = self.position();
+        let start_span = self.next_span();
+        let start_depth = self.depth;
+        let result = match self.peek().cloned() {
+                Some(peek_token) => self.parse_lle_work(peek_token),
+                None             => {
+                    self.expect("an expression".to_string());
+                    Err(self.unexpected())
+                },
+            };
+        let e = match result {
+                Ok(e)    => e,
+                Err(err) => {
+                    self.report(err);
+                    self.recover(start_depth)?;
+                    LetLangExp::ErrorExp
+                },
+            };
+        let span = if self.position() > start { self.span_from(start_span) } else { start_span };
+        Ok(LetLangExp::new_spanned_exp(&span, &e))
+    }
+    // keeps err unless an error has already been reported at its token,
+    // which would only be a consequence of that one
+    fn report(&mut self, err: ParseErr) {
+        if self.errors.last().map_or(true, |last| last.span.start != err.span.start) {
+            self.errors.push(err);
+        }
+    }
+    // panic mode, for the expression, begun at bracket depth start_depth,
+    // that ran into an error. If it left brackets open, skips up to and including the token that closes
+    // them, and then the expression after an in or else, which is parsed for
+    // its own errors; otherwise skips to the next in, then, else, closing
+    // bracket, comma or semicolon outside of any brackets
+    fn recover(&mut self, start_depth: i32) -> Result<(), ParseErr> {
+        let open = (self.depth - start_depth).max(0);
+        let mut depth = open;
+        while let Some(n) = self.peek().map(nesting) {
+            if open == 0 && depth == 0 {
+                match self.peek() {
+                    Some(&TokenKind::Then) | Some(&TokenKind::Comma) | Some(&TokenKind::Semicolon) => return Ok(()),
+                    _ if n < 0                                                                => return Ok(()),
+                    _                                                                         => (),
+                }
+            }
+            let has_body = self.peek() == Some(&TokenKind::In) || self.peek() == Some(&TokenKind::Else);
+            self.advance();
+            depth += n;
+            if open > 0 && depth == 0 {
+                if has_body {
+                    self.parse_let_lang_exp()?;
+                }
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+    // the count of tokens consumed, which is the index of the next one
+    fn position(&self) -> usize {
+        self.consumed
+    }
+    // pulls tokens from the source until n are waiting, or there are no more
+    fn fill(&mut self, n: usize) {
+        while self.lookahead.len() < n {
+            match self.tokens.next() {
+                Some(token) => self.lookahead.push_back(token),
+                None        => break,
+            }
+        }
+    }
+    // the next token, left in place; there is always one waiting unless the
+    // input has ended
+    fn peek(&self) -> Option<&TokenKind<'src>> {
+        self.lookahead.front().map(|t| &t.kind)
+    }
+    // consumes the next token
+    fn advance(&mut self) -> Option<Token<'src>> {
+        let token = self.lookahead.pop_front()?;
+        self.consumed += 1;
+        self.depth += nesting(&token.kind);
+        self.last = token.span;
+        self.end = token.end_span();
+        self.fill(1);
+        Some(token)
+    }
+    // consumes the next token, an identifier or string, for its text; this
+    // is where an identifier borrowed from the source is copied, once, for
+    // the AST
+    fn take_text(&mut self) -> String {
+        match self.advance().map(|t| t.kind) {
+            Some(TokenKind::Identifier(s)) => s.into_owned(),
+            Some(TokenKind::Str(s))        => s,
+            _                              => String::new(),
+        }
+    }
+    // records that what would have been valid at the current position
+    fn expect(&mut self, what: String) {
+        if self.expected_pos != self.position() {
+            self.expected.clear();
+            self.expected_pos = self.position();
+        }
+        if !self.expected.contains(&what) {
+            self.expected.push(what);
+        }
+    }
+    // is the next token of this kind? If not, the kind is expected here
+    fn at(&mut self, kind: &TokenKind<'src>) -> bool {
+        if self.peek() == Some(kind) {
+            return true;
+        }
+        self.expect(kind.describe());
+        false
+    }
+    // consumes the next token, which must be one of kinds
+    fn next_of(&mut self, kinds: &[TokenKind<'src>]) -> Result<TokenKind<'src>, ParseErr> {
+        for kind in kinds {
+            if self.at(kind) {
+                self.advance();
+                return Ok(kind.clone());
             }
+        }
+        Err(self.unexpected())
+    }
+    // the error for the next token, naming what was expected in its place
+    fn unexpected(&self) -> ParseErr {
+        let pos = self.position();
+        let found = match self.peek() {
+                        Some(tok) => tok.describe(),
+                        None      => "end of input".to_string(),
+                    };
+        let expected = if self.expected_pos == pos { self.expected.clone() } else { Vec::new() };
+        let message = match expected.len() {
+                          0 => format!("unexpected {}", found),
+                          1 => format!("expected {}, found {}", expected[0], found),
+                          n => format!("expected one of {} or {}, found {}",
+                                       expected[..n - 1].join(", "), expected[n - 1], found),
+                      };
+        ParseErr { message: message, expected: expected, span: self.next_span() }
     }
-    fn parse_lle_work(&mut self, peek_tok: Token) -> Result<LetLangExp, ParseErr> {
+    fn error_at(&self, span: Span, message: String) -> ParseErr {
+        ParseErr { message: message, expected: Vec::new(), span: span }
+    }
+    // the span of the next token; the end of input is just past the last token
+    fn next_span(&self) -> Span {
+        match self.lookahead.front() {
+            Some(token) => token.span,
+            None        => self.end,
+        }
+    }
+    // the span from start to the end of the last token parsed
+    fn span_from(&self, start: Span) -> Span {
+        start.to(&self.last)
+    }
+    fn parse_lle_work(&mut self, peek_tok: TokenKind<'src>) -> Result<LetLangExp, ParseErr> {
 //        println!("Peek token: {:?}", peek_tok);  // for debugging
         match peek_tok {  // try returns Err(ParseErr) on early return
-            Token::Integer(_n)    => {
+            TokenKind::Integer(_n)    => {
                                       let e: LetLangExp = self.parse_const()?;
                                       Ok(e)},
-            Token::Boolean(_b)    => {
+            TokenKind::Boolean(_b)    => {
                                       let e = self.parse_bool()?;
                                       Ok(e)},
-            Token::Minus          => { // diff_exp
+            TokenKind::Minus          => { // diff_exp
                                       let e = self.parse_diff()?;
                                       Ok(e)
                                     },
-            Token::IsZero         => { // iszero exp
+            TokenKind::IsZero         => { // iszero exp
                                       let e = self.parse_iszero()?;
                                       Ok(e)
                                     },
-            Token::If             => { // If-then-else expression
+            TokenKind::If             => { // If-then-else expression
                                       let e = self.parse_if_then_else()?;
                                       Ok(e)
                                     },
-            Token::Identifier(_s) => { // identifier cannot be a reserved word
+            TokenKind::Identifier(_s) => { // identifier cannot be a reserved word
                                       let e = self.parse_var()?;
                                       Ok(e)
                                     },
-            Token::Let            => { // let-in expression
+            TokenKind::Let            => { // let-in expression
                                       let e = self.parse_let_in()?;
                                       Ok(e)
                                     },
-                            _ => parse_err!("lle: Unexpected token type"),
+            TokenKind::While          => { // while test do body
+                                      let e = self.parse_while()?;
+                                      Ok(e)
+                                    },
+            TokenKind::Set            => { // set x = e
+                                      let e = self.parse_set()?;
+                                      Ok(e)
+                                    },
+            TokenKind::Proc           => { // proc (x : type, ...) body
+                                      let e = self.parse_proc()?;
+                                      Ok(e)
+                                    },
+            TokenKind::From           => { // from m take x
+                                      let start = self.next_span();
+                                      self.match_token(&TokenKind::From)?;
+                                      let m = self.get_string()?;
+                                      self.match_token(&TokenKind::Take)?;
+                                      let s = self.get_string()?;
+                                      let var_exp = LetLangExp::new_qualified_var_exp(&m, &s);
+                                      let var_exp = LetLangExp::new_spanned_exp(&self.span_from(start), &var_exp);
+                                      if self.at(&TokenKind::Lparen) {
+                                          let rands = self.parse_call_args()?;
+                                          return Ok(LetLangExp::new_call_exp(&var_exp, &rands));
+                                      }
+                                      Ok(var_exp)
+                                    },
+            TokenKind::New            => { // new c(e1, ..., en)
+                                      self.match_token(&TokenKind::New)?;
+                                      let c = self.get_string()?;
+                                      let rands = self.parse_call_args()?;
+                                      Ok(LetLangExp::new_object_exp(&c, &rands))
+                                    },
+            TokenKind::Send           => { // send obj m(e1, ..., en)
+                                      self.match_token(&TokenKind::Send)?;
+                                      let obj = self.parse_let_lang_exp()?;
+                                      let m = self.get_string()?;
+                                      let rands = self.parse_call_args()?;
+                                      Ok(LetLangExp::new_method_call_exp(&obj, &m, &rands))
+                                    },
+            TokenKind::Super          => { // super m(e1, ..., en)
+                                      self.match_token(&TokenKind::Super)?;
+                                      let m = self.get_string()?;
+                                      let rands = self.parse_call_args()?;
+                                      Ok(LetLangExp::new_super_call_exp(&m, &rands))
+                                    },
+            TokenKind::SelfKw         => {
+                                      self.match_token(&TokenKind::SelfKw)?;
+                                      Ok(LetLangExp::SelfExp)
+                                    },
+            TokenKind::Cast           => { // cast e c
+                                      self.match_token(&TokenKind::Cast)?;
+                                      let e = self.parse_let_lang_exp()?;
+                                      let c = self.get_string()?;
+                                      Ok(LetLangExp::new_cast_exp(&e, &c))
+                                    },
+            TokenKind::InstanceOf     => { // instanceof e c
+                                      self.match_token(&TokenKind::InstanceOf)?;
+                                      let e = self.parse_let_lang_exp()?;
+                                      let c = self.get_string()?;
+                                      Ok(LetLangExp::new_instance_of_exp(&e, &c))
+                                    },
+            TokenKind::Perform        => { // perform op(e)
+                                      self.match_token(&TokenKind::Perform)?;
+                                      let op = self.get_string()?;
+                                      self.match_token(&TokenKind::Lparen)?;
+                                      let e = self.parse_let_lang_exp()?;
+                                      self.match_token(&TokenKind::Rparen)?;
+                                      Ok(LetLangExp::new_perform_exp(&op, &e))
+                                    },
+            TokenKind::Handle         => { // handle e with {clause, ...}
+                                      let e = self.parse_handle()?;
+                                      Ok(e)
+                                    },
+            TokenKind::Generator      => { // generator e
+                                      self.match_token(&TokenKind::Generator)?;
+                                      let e = self.parse_let_lang_exp()?;
+                                      Ok(LetLangExp::new_generator_exp(&e))
+                                    },
+            TokenKind::Yield          => { // yield e
+                                      self.match_token(&TokenKind::Yield)?;
+                                      let e = self.parse_let_lang_exp()?;
+                                      Ok(LetLangExp::new_yield_exp(&e))
+                                    },
+            TokenKind::Next           => {
+                                      let args = self.parse_prim_args(&TokenKind::Next, 1)?;
+                                      Ok(LetLangExp::new_next_exp(&args[0]))
+                                    },
+            TokenKind::IsDone         => {
+                                      let args = self.parse_prim_args(&TokenKind::IsDone, 1)?;
+                                      Ok(LetLangExp::new_is_done_exp(&args[0]))
+                                    },
+            TokenKind::Amb            => { // amb(e1, ..., en)
+                                      self.match_token(&TokenKind::Amb)?;
+                                      let choices = self.parse_call_args()?;
+                                      Ok(LetLangExp::new_amb_exp(&choices))
+                                    },
+            TokenKind::Require        => {
+                                      let args = self.parse_prim_args(&TokenKind::Require, 1)?;
+                                      Ok(LetLangExp::new_require_exp(&args[0]))
+                                    },
+            TokenKind::Flip           => {
+                                      let args = self.parse_prim_args(&TokenKind::Flip, 1)?;
+                                      Ok(LetLangExp::new_flip_exp(&args[0]))
+                                    },
+            TokenKind::Observe        => {
+                                      let args = self.parse_prim_args(&TokenKind::Observe, 1)?;
+                                      Ok(LetLangExp::new_observe_exp(&args[0]))
+                                    },
+            TokenKind::Quote          => { // quote e
+                                      self.match_token(&TokenKind::Quote)?;
+                                      let e = self.parse_let_lang_exp()?;
+                                      Ok(LetLangExp::new_quote_exp(&e))
+                                    },
+            TokenKind::Splice         => { // splice e
+                                      self.match_token(&TokenKind::Splice)?;
+                                      let e = self.parse_let_lang_exp()?;
+                                      Ok(LetLangExp::new_splice_exp(&e))
+                                    },
+            TokenKind::Run            => { // run e
+                                      self.match_token(&TokenKind::Run)?;
+                                      let e = self.parse_let_lang_exp()?;
+                                      Ok(LetLangExp::new_run_exp(&e))
+                                    },
+            TokenKind::Assert         => { // assert(test, "message")
+                                      self.match_token(&TokenKind::Assert)?;
+                                      self.match_token(&TokenKind::Lparen)?;
+                                      let test = self.parse_let_lang_exp()?;
+                                      self.match_token(&TokenKind::Comma)?;
+                                      let message = match self.peek() {
+                                          Some(&TokenKind::Str(_)) => self.take_text(),
+                                          _                        => {
+                                              self.expect("a string".to_string());
+                                              return Err(self.unexpected());
+                                          },
+                                      };
+                                      self.match_token(&TokenKind::Rparen)?;
+                                      Ok(LetLangExp::new_assert_exp(&test, &message))
+                                    },
+            TokenKind::CollectAll     => { // collect-all e
+                                      self.match_token(&TokenKind::CollectAll)?;
+                                      let e = self.parse_let_lang_exp()?;
+                                      Ok(LetLangExp::new_collect_all_exp(&e))
+                                    },
+            TokenKind::Begin          => { // begin e1; e2; ... end
+                                      let e = self.parse_begin()?;
+                                      Ok(e)
+                                    },
+            TokenKind::Str(_s)        => {
+                                      let e = self.parse_str()?;
+                                      Ok(e)},
+            TokenKind::Lbrace         => { // dictionary literal
+                                      let e = self.parse_dict()?;
+                                      Ok(e)
+                                    },
+            TokenKind::DictGet        => {
+                                      let args = self.parse_prim_args(&TokenKind::DictGet, 2)?;
+                                      Ok(LetLangExp::new_dict_get(&args[0], &args[1]))
+                                    },
+            TokenKind::DictSet        => {
+                                      let args = self.parse_prim_args(&TokenKind::DictSet, 3)?;
+                                      Ok(LetLangExp::new_dict_set(&args[0], &args[1], &args[2]))
+                                    },
+            TokenKind::DictHas        => {
+                                      let args = self.parse_prim_args(&TokenKind::DictHas, 2)?;
+                                      Ok(LetLangExp::new_dict_has(&args[0], &args[1]))
+                                    },
+            TokenKind::DictRemove     => {
+                                      let args = self.parse_prim_args(&TokenKind::DictRemove, 2)?;
+                                      Ok(LetLangExp::new_dict_remove(&args[0], &args[1]))
+                                    },
+            TokenKind::DictKeys       => {
+                                      let args = self.parse_prim_args(&TokenKind::DictKeys, 1)?;
+                                      Ok(LetLangExp::new_dict_keys(&args[0]))
+                                    },
+                            _ => {
+                                      self.expect("an expression".to_string());
+                                      Err(self.unexpected())
+                                    },
             }
     }
     // build AST fragment for const
     fn parse_const(&mut self) -> Result<LetLangExp, ParseErr> {
-        let val: i32;
-        let option_tok: Option<&Token> = self.tokens.next();
-        // Extract the token from Some() if found, else return with Err(ParseErr)
-        let tok = match option_tok {
-                    Some(t) => t,
-                    _       => parse_err!("parse_integer: Int expected but EOI found."),
-                    };
-        // extract the integer value
-        match tok.clone() {
-            Token::Integer(i) => val = i,
-            _                 => parse_err!("parse_const: Int token expected."),
-        };
-        Ok(LetLangExp::new_const_exp(val))  // Final returned value
-    }
-   // build AST fragment for boolean
-   fn parse_bool(&mut self) -> Result<LetLangExp, ParseErr> {
-        let val: bool;
-        let option_tok: Option<&Token> = self.tokens.next();
-        // Extract the token from Some() if found, else return with Err(ParseErr)
-        let tok = match option_tok {
-                    Some(t) => t,
-                    _       => parse_err!("parse_bool: Bool expected but EOI found."),
-                    };
-        // extract the boolean value
-        match tok.clone() {
-            Token::Boolean(b) => val = b,
-            _                 => parse_err!("parse_bool: Identifier token expected."),
-        };
-        Ok(LetLangExp::new_boolean(val))
+        match self.peek() {
+            Some(&TokenKind::Integer(i)) => {
+                self.advance();
+                Ok(LetLangExp::new_const_exp(i))
+            },
+            _                            => {
+                self.expect("an integer".to_string());
+                Err(self.unexpected())
+            },
+        }
+    }
+    // build AST fragment for boolean
+    fn parse_bool(&mut self) -> Result<LetLangExp, ParseErr> {
+        match self.peek() {
+            Some(&TokenKind::Boolean(b)) => {
+                self.advance();
+                Ok(LetLangExp::new_boolean(b))
+            },
+            _                            => {
+                self.expect("a boolean".to_string());
+                Err(self.unexpected())
+            },
+        }
+    }
+    // build AST fragment for string literal
+    fn parse_str(&mut self) -> Result<LetLangExp, ParseErr> {
+        match self.peek() {
+            Some(&TokenKind::Str(_)) => Ok(LetLangExp::new_str_exp(&self.take_text())),
+            _                            => {
+                self.expect("a string".to_string());
+                Err(self.unexpected())
+            },
+        }
     }
     // build AST fragment for variable
     fn parse_var(&mut self) -> Result<LetLangExp, ParseErr> {
-        let var: String;
-        let option_tok: Option<&Token> = self.tokens.next();
-        // Extract the token from Some() if found, else return with Err(ParseErr)
-        let tok = match option_tok {
-                    Some(t) => t,
-                    _       => parse_err!("parse_var: Ident expected but EOI found."),
-                    };
-        // extract the variable name string
-        match tok.clone() {
-            Token::Identifier(s) => var = s,
-            _                    => parse_err!("parse_var: Identifier token expected."),
-        };
-        Ok(LetLangExp::new_var_exp(&var))
+        let start = self.next_span();
+        let var_exp = LetLangExp::VarExp(self.get_string()?);  // moved, not copied
+        if self.at(&TokenKind::Lparen) { // procedure call
+            let var_exp = LetLangExp::new_spanned_exp(&self.span_from(start), &var_exp);
+            let rands = self.parse_call_args()?;
+            return Ok(LetLangExp::new_call_exp(&var_exp, &rands));
+        }
+        Ok(var_exp)
+    }
+    // (e1, ..., en), possibly empty
+    fn parse_call_args(&mut self) -> Result<Vec<LetLangExp>, ParseErr> {
+        self.match_token(&TokenKind::Lparen)?;
+        let mut rands = Vec::new();
+        if self.at(&TokenKind::Rparen) {
+            self.match_token(&TokenKind::Rparen)?;
+            return Ok(rands);
+        }
+        loop {
+            rands.push(self.parse_let_lang_exp()?);
+            if self.next_of(&[TokenKind::Comma, TokenKind::Rparen])? == TokenKind::Rparen {
+                break;
+            }
+        }
+        Ok(rands)
     }
 
     // Gets the variable name.
     // Advances input stream by one token.
     // Used in parse_let_in.
     fn get_string(&mut self) -> Result<String, ParseErr> {
-        let option_tok: Option<&Token> = self.tokens.next();
-
-        // Extracts the cloned token from the Option
-        let tok: Token = match option_tok {
-                            Some(token) => token.clone(),
-                            _ => parse_err!("get_string1: Unexpected EOI")
-                            };
-        // Returns the Identifier in result form.
-        let var: String =
-            match tok {
-                Token::Identifier(s) => s,
-                _                    => parse_err!("get_string2: Unexpected token type"),
-                };
-        Ok(var)     // var is a String
+        match self.peek() {
+            Some(&TokenKind::Identifier(_)) => Ok(self.take_text()),
+            _                               => {
+                self.expect("an identifier".to_string());
+                Err(self.unexpected())
+            },
         }
+    }
 
     // Matches to the next token if it is a specific type.
     // If successful, the input stream is advanced but the result is not used.
     // Used in parse_diff, parse_iszero, parse_if_then_else, parse_let_in.
-    fn match_token(&mut self, tok: &Token)  -> Result<Option<LetLangExp>, ParseErr> {
-        let option_tok: Option<&Token> = self.tokens.next();
-        match option_tok {
-             Some(tok2) => {if tok == tok2 { // checks if the token type matches
-                                Ok(None)
-                            } else {
-                                parse_err!("Expected {:?} but found {:?}", tok, tok2)
-                            }},
-             _            => parse_err!("Expected {:?} but found EOI", tok)
-         }}
+    fn match_token(&mut self, tok: &TokenKind<'src>)  -> Result<Option<LetLangExp>, ParseErr> {
+        if self.at(tok) { // checks if the token type matches
+            self.advance();
+            Ok(None)
+        } else {
+            Err(self.unexpected())
+        }}
     // build AST fragment for diff expression
     fn parse_diff(&mut self) -> Result<LetLangExp, ParseErr> {
-        try!(self.match_token(&Token::Minus));   // return with Err(ParseErr) if no match
-        try!(self.match_token(&Token::Lparen));
+        try!(self.match_token(&TokenKind::Minus));   // return with Err(ParseErr) if no match
+        try!(self.match_token(&TokenKind::Lparen));
         let e1 = try!(self.parse_let_lang_exp()); // return with Err(ParseErr) if no parse
-        try!(self.match_token(&Token::Comma));
+        try!(self.match_token(&TokenKind::Comma));
         let e2 = try!(self.parse_let_lang_exp());
-        try!(self.match_token(&Token::Rparen));
+        try!(self.match_token(&TokenKind::Rparen));
         Ok(LetLangExp::new_diff_exp(&e1, &e2))   // return Ok(LetLangExp::new_diff_exp())
     }
     fn parse_iszero(&mut self) -> Result<LetLangExp, ParseErr> {
-        self.match_token(&Token::IsZero)?;
-        self.match_token(&Token::Lparen)?;
+        self.match_token(&TokenKind::IsZero)?;
+        self.match_token(&TokenKind::Lparen)?;
         let e = self.parse_let_lang_exp()?;
-        self.match_token(&Token::Rparen)?;
+        self.match_token(&TokenKind::Rparen)?;
         Ok(LetLangExp::new_iszero(&e))
     }
     fn parse_if_then_else(&mut self) -> Result<LetLangExp, ParseErr> {
-        self.match_token(&Token::If)?;
+        self.match_token(&TokenKind::If)?;
         let e1 = self.parse_let_lang_exp()?;
-        self.match_token(&Token::Then)?;
+        self.match_token(&TokenKind::Then)?;
         let e2 = self.parse_let_lang_exp()?;
-        self.match_token(&Token::Else)?;
+        self.match_token(&TokenKind::Else)?;
         let e3 = self.parse_let_lang_exp()?;
         Ok(LetLangExp::new_if_exp(&e1, &e2, &e3))
     }
+    // {k1: v1, k2: v2, ...}; the empty dictionary is {}
+    fn parse_dict(&mut self) -> Result<LetLangExp, ParseErr> {
+        self.match_token(&TokenKind::Lbrace)?;
+        let mut entries: Vec<(LetLangExp, LetLangExp)> = Vec::new();
+        if self.at(&TokenKind::Rbrace) {
+            self.match_token(&TokenKind::Rbrace)?;
+            return Ok(LetLangExp::new_dict_exp(&entries));
+        }
+        loop {
+            let k = self.parse_let_lang_exp()?;
+            self.match_token(&TokenKind::Colon)?;
+            let v = self.parse_let_lang_exp()?;
+            entries.push((k, v));
+            if self.next_of(&[TokenKind::Comma, TokenKind::Rbrace])? == TokenKind::Rbrace {
+                break;
+            }
+        }
+        Ok(LetLangExp::new_dict_exp(&entries))
+    }
+    // handle e with {clause, ..., clause}
+    // clause ::= op(x, k) -> exp | return(x) -> exp
+    fn parse_handle(&mut self) -> Result<LetLangExp, ParseErr> {
+        self.match_token(&TokenKind::Handle)?;
+        let body = self.parse_let_lang_exp()?;
+        self.match_token(&TokenKind::With)?;
+        self.match_token(&TokenKind::Lbrace)?;
+        let mut handler = Handler { clauses: Vec::new(), ret: None };
+        loop {
+            let op_span = self.next_span();
+            let op = self.get_string()?;
+            self.match_token(&TokenKind::Lparen)?;
+            let param = self.get_string()?;
+            if &op[..] == "return" {
+                self.match_token(&TokenKind::Rparen)?;
+                self.match_token(&TokenKind::Arrow)?;
+                if handler.ret.is_some() {
+                    parse_err!(self, op_span, "a handler has at most one return clause");
+                }
+                handler.ret = Some((param, Rc::new(self.parse_let_lang_exp()?)));
+            } else {
+                self.match_token(&TokenKind::Comma)?;
+                let resume = self.get_string()?;
+                self.match_token(&TokenKind::Rparen)?;
+                self.match_token(&TokenKind::Arrow)?;
+                let body = self.parse_let_lang_exp()?;
+                handler.clauses.push(OpClause { op: op, param: param, resume: resume, body: Rc::new(body) });
+            }
+            if self.next_of(&[TokenKind::Comma, TokenKind::Rbrace])? == TokenKind::Rbrace {
+                break;
+            }
+        }
+        Ok(LetLangExp::new_handle_exp(&body, &handler))
+    }
+    // begin e1; e2; ... end, with at least one expression and no trailing ";"
+    fn parse_begin(&mut self) -> Result<LetLangExp, ParseErr> {
+        self.match_token(&TokenKind::Begin)?;
+        let mut exps = vec![self.parse_let_lang_exp()?];
+        while self.next_of(&[TokenKind::Semicolon, TokenKind::End])? == TokenKind::Semicolon {
+            exps.push(self.parse_let_lang_exp()?);
+        }
+        Ok(LetLangExp::new_begin_exp(&exps))
+    }
+    // prim(arg1, ..., argn) for primitives with a fixed number of arguments
+    fn parse_prim_args(&mut self, prim: &TokenKind<'src>, n: usize) -> Result<Vec<LetLangExp>, ParseErr> {
+        self.match_token(prim)?;
+        self.match_token(&TokenKind::Lparen)?;
+        let mut args = Vec::new();
+        for i in 0..n {
+            if i > 0 { self.match_token(&TokenKind::Comma)?; }
+            args.push(self.parse_let_lang_exp()?);
+        }
+        self.match_token(&TokenKind::Rparen)?;
+        Ok(args)
+    }
     fn parse_let_in(&mut self) -> Result<LetLangExp, ParseErr> {
-        self.match_token(&Token::Let)?;
+        self.match_token(&TokenKind::Let)?;
         let s = self.get_string()?;               // match variable name
-        self.match_token(&Token::Assign)?;        // match "="
+        if self.at(&TokenKind::Lparen) {
+            return self.parse_named_let(&s);
+        }
+        let contract = if self.at(&TokenKind::Colon) {
+                           self.match_token(&TokenKind::Colon)?;
+                           Some(self.parse_contract()?)
+                       } else {
+                           None
+                       };
+        self.match_token(&TokenKind::Assign)?;        // match "="
         let e1 = self.parse_let_lang_exp()?;
-        self.match_token(&Token::In)?;
+        self.match_token(&TokenKind::In)?;
         let e2 = self.parse_let_lang_exp()?;
-        Ok(LetLangExp::new_let_exp(&s, &e1, &e2))
+        match contract {
+            Some(c) => Ok(LetLangExp::new_contract_let_exp(&s, &c, &e1, &e2)),
+            None    => Ok(LetLangExp::new_let_exp(&s, &e1, &e2)),
+        }
+    }
+    // contract ::= predicate | ( contract, ... -> contract )
+    fn parse_contract(&mut self) -> Result<Contract, ParseErr> {
+        match self.peek() {
+            Some(&TokenKind::Identifier(_)) => Ok(Contract::Flat(self.take_text())),
+            Some(&TokenKind::Lparen)            => {
+                self.advance();
+                let mut args = Vec::new();
+                if !self.at(&TokenKind::Arrow) {
+                    loop {
+                        args.push(Rc::new(self.parse_contract()?));
+                        if !self.at(&TokenKind::Comma) { break; }
+                        self.match_token(&TokenKind::Comma)?;
+                    }
+                }
+                self.match_token(&TokenKind::Arrow)?;
+                let result = self.parse_contract()?;
+                self.match_token(&TokenKind::Rparen)?;
+                Ok(Contract::Arrow(args, Rc::new(result)))
+            },
+            _                               => {
+                self.expect("a contract".to_string());
+                Err(self.unexpected())
+            },
+        }
+    }
+    // let loop (v1 = e1, ..., vn = en) in body; "let loop" has already been matched
+    fn parse_named_let(&mut self, name: &String) -> Result<LetLangExp, ParseErr> {
+        self.match_token(&TokenKind::Lparen)?;
+        let mut bindings: Vec<(String, LetLangExp)> = Vec::new();
+        loop {
+            let v = self.get_string()?;
+            self.match_token(&TokenKind::Assign)?;
+            let e = self.parse_let_lang_exp()?;
+            bindings.push((v, e));
+            if self.next_of(&[TokenKind::Comma, TokenKind::Rparen])? == TokenKind::Rparen {
+                break;
+            }
+        }
+        self.match_token(&TokenKind::In)?;
+        let body = self.parse_let_lang_exp()?;
+        Ok(LetLangExp::new_named_let_exp(name, &bindings, &body))
+    }
+    fn parse_while(&mut self) -> Result<LetLangExp, ParseErr> {
+        self.match_token(&TokenKind::While)?;
+        let test = self.parse_let_lang_exp()?;
+        self.match_token(&TokenKind::Do)?;
+        let body = self.parse_let_lang_exp()?;
+        Ok(LetLangExp::new_while_exp(&test, &body))
+    }
+    fn parse_set(&mut self) -> Result<LetLangExp, ParseErr> {
+        self.match_token(&TokenKind::Set)?;
+        let s = self.get_string()?;
+        self.match_token(&TokenKind::Assign)?;
+        let e = self.parse_let_lang_exp()?;
+        Ok(LetLangExp::new_set_exp(&s, &e))
+    }
+    // parameter types are optional; the checker treats a missing one as any
+    fn parse_proc(&mut self) -> Result<LetLangExp, ParseErr> {
+        self.match_token(&TokenKind::Proc)?;
+        let params = self.parse_params()?;
+        let body = self.parse_let_lang_exp()?;
+        Ok(LetLangExp::new_proc_exp(&params, &body))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn programs_are_defines_then_an_expression() {
+        let pgm = parse(&tokenize("define a = 1\ndefine b = -(a, 1)\n-(b, a)").unwrap()).unwrap();
+        assert_eq!(pgm.defs.len(), 2);
+        assert!(pgm.body.is_some());
+        assert_eq!(format!("{}", pgm), "define a = 1\ndefine b = -(a, 1)\n-(b, a)");
+        let no_body = parse(&tokenize("define a = 1 define a = 2").unwrap()).unwrap();
+        assert_eq!(no_body.defs.len(), 2);
+        assert!(no_body.body.is_none());
+        let early = parse(&tokenize("define a = b define b = 1").unwrap()).unwrap();
+        assert_eq!(format!("{}", early), "define a = b\ndefine b = 1\n");
+        assert!(parse(&tokenize("define = 1").unwrap()).is_err());
+    }
+
+    #[test]
+    fn expressions_carry_the_spans_of_their_tokens() {
+        let pgm = parse(&tokenize("let f = proc (y) y\nin f(-(2, 1))").unwrap()).unwrap();
+        let body = pgm.body.unwrap();
+        let (span, e) = match *body {
+            LetLangExp::SpannedExp(span, ref e) => (span, e.clone()),
+            _                                   => panic!("body has no span"),
+        };
+        assert_eq!((span.start, span.end, span.line, span.column), (0, 32, 1, 1));
+        let call = match *e {
+            LetLangExp::LetExp(_, _, ref body) => body.clone(),
+            _                                  => panic!("not a let"),
+        };
+        match *call {
+            LetLangExp::SpannedExp(span, ref call) => {
+                assert_eq!((span.start, span.end, span.line, span.column), (22, 32, 2, 4));
+                match **call {
+                    LetLangExp::CallExp(ref rator, _) =>
+                        assert_eq!(**rator, LetLangExp::SpannedExp(Span { start: 22, end: 23, line: 2, column: 4, utf16_column: 4,
+                                                                                            end_line: 2, end_column: 5, end_utf16_column: 5 },
+                                                                   Rc::new(LetLangExp::VarExp("f".to_string())))),
+                    _ => panic!("not a call"),
+                }},
+            _ => panic!("call has no span"),
+        }
+    }
+
+    fn parse_error(src: &str) -> ParseErr {
+        parse(&tokenize(src).unwrap()).err().expect("should not parse").remove(0)
+    }
+
+    #[test]
+    fn errors_list_what_was_expected() {
+        let e = parse_error("let x = y x");
+        assert_eq!(e.message, "expected one of `(` or `in`, found identifier `x`");
+        assert_eq!(e.expected, vec!["`(`", "`in`"]);
+        assert_eq!((e.span.start, e.span.end, e.span.line, e.span.column), (10, 11, 1, 11));
+        assert_eq!(parse_error("begin 1; end").message, "expected an expression, found `end`");
+        assert_eq!(parse_error("f(1, 2").message, "expected one of `,` or `)`, found end of input");
+        assert_eq!(parse_error("").message,
+                   "expected one of `define`, `module`, `class`, `interface` or an expression, found end of input");
+        assert_eq!(parse_error("-(1, 2) 3").message, "expected end of input, found integer `3`");
+        let e = parse_error("-(1, имя");
+        assert_eq!((e.span.start, e.span.column, e.span.utf16_column), (11, 9, 9));
+        // the end of input is placed by the source text, not by the token's value
+        let e = parse_error("f(\"a\\n\\\"b\"");
+        assert_eq!((e.span.start, e.span.line, e.span.column), (10, 1, 11));
+        let e = parse_error("f(cafe\u{301}");
+        assert_eq!((e.span.start, e.span.column, e.span.utf16_column), (8, 8, 8));
+        let e = parse_error("f(\"a\nb\"");
+        assert_eq!((e.span.line, e.span.column), (2, 3));
+    }
+
+    #[test]
+    fn errors_render_the_offending_source() {
+        let src = "let f = proc (y) y\nin f(2 3)";
+        assert_eq!(parse_error(src).render(src),
+                   "ParseError: expected one of `,` or `)`, found integer `3` (line: 2, column: 8)\n  |\n2 | in f(2 3)\n  |        ^");
+        let src = "if zero?(0) then 1";
+        assert_eq!(parse_error(src).render(src),
+                   "ParseError: expected `else`, found end of input (line: 1, column: 19)\n  |\n1 | if zero?(0) then 1\n  |                   ^");
+    }
+
+    #[test]
+    fn recovery_reports_every_syntax_error() {
+        let src = "let x = -(1 2) in\nlet f = proc (y) if zero?(y) 1 else 2 in\nf(x, , begin 3; end)";
+        let errors = parse(&tokenize(src).unwrap()).err().expect("should not parse");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec!["ParseError: expected `,`, found integer `2` (line: 1, column: 13)",
+                                  "ParseError: expected `then`, found integer `1` (line: 2, column: 30)",
+                                  "ParseError: expected an expression, found `,` (line: 3, column: 6)",
+                                  "ParseError: expected an expression, found `end` (line: 3, column: 17)"]);
+    }
+
+    #[test]
+    fn recovered_expressions_become_error_nodes() {
+        let (pgm, errors) = parse_recovering(&tokenize("define a = -(1 2)\ndefine 5 = 1\ndefine b = a\nf(a, )").unwrap());
+        assert_eq!(errors.len(), 3);
+        assert_eq!(pgm.defs.len(), 2);
+        assert_eq!(pgm.body.unwrap().to_string(), "f(a, <error>)");
+        // a stray closing bracket is reported once, not once per enclosing expression
+        assert_eq!(parse(&tokenize("let x = 1 in minus)").unwrap()).err().unwrap().len(), 1);
+        // a bad parameter list does not lose the body after it
+        let (pgm, errors) = parse_recovering(&tokenize("proc (x y) x").unwrap());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "ParseError: expected one of `:`, `,` or `)`, found identifier `y` (line: 1, column: 9)");
+        assert_eq!(pgm.body.unwrap().to_string(), "proc (x) x");
+        assert_eq!(parse(&tokenize("proc (x y x").unwrap()).err().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn the_parser_works_around_lexical_errors() {
+        let (tokens, lex_errors) = tokenize_recovering("let x = 1 in -(x, 2#)");
+        assert_eq!(lex_errors.len(), 1);
+        let (pgm, errors) = parse_recovering(&tokens);
+        let messages: Vec<&str> = errors.iter().map(|e| &e.message[..]).collect();
+        assert_eq!(messages, vec!["expected `)`, found invalid input"]);
+        assert_eq!(pgm.body.unwrap().to_string(), "let x = 1 in <error>");
+    }
+
+    #[test]
+    fn programs_parse_from_a_reader_as_they_are_read() {
+        // begin 0; 1; ...; n end, made up as it is read
+        struct Counting { next: usize, n: usize, text: Vec<u8> }
+        impl ::std::io::Read for Counting {
+            fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+                while self.text.len() < buf.len() && self.next <= self.n + 1 {
+                    let more = match self.next {
+                                   0                    => "begin 0".to_string(),
+                                   i if i <= self.n     => format!("; {}", i),
+                                   _                    => " end".to_string(),
+                               };
+                    self.text.extend(more.bytes());
+                    self.next += 1;
+                }
+                let len = buf.len().min(self.text.len());
+                buf[..len].copy_from_slice(&self.text[..len]);
+                self.text.drain(..len);
+                Ok(len)
+            }
+        }
+        let pgm = parse_reader(Counting { next: 0, n: 200000, text: Vec::new() }).unwrap();
+        match *pgm.body.unwrap() {
+            LetLangExp::SpannedExp(span, ref e) => {
+                assert_eq!(span.start, 0);
+                match **e {
+                    LetLangExp::BeginExp(ref exps) => assert_eq!(exps.len(), 200001),
+                    _                              => panic!("not a begin"),
+                }},
+            _ => panic!("body has no span"),
+        }
+        match parse_reader("let x = 1 in -(x, 2#)".as_bytes()) {
+            Err(SyntaxErr::Lex(err)) => assert_eq!(err.to_string(), "LexError: Unexpected character: # (line: 1, column: 20)"),
+            other                    => panic!("expected a lexical error, got {:?}", other.map(|p| p.to_string())),
+        }
+        match parse_reader("let x = 1 x".as_bytes()) {
+            Err(SyntaxErr::Parse(errs)) => assert_eq!(errs.len(), 1),
+            other                       => panic!("expected a syntax error, got {:?}", other.map(|p| p.to_string())),
+        }
+    }
+
+    // lexes and parses a program of several MB both ways, as the parser
+    // copies each identifier it keeps, so that the lexer's saving is seen
+    // against the whole of the work
+    // cargo test --release -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn benchmark_borrowed_against_copied_identifiers() {
+        use std::time::{Duration, Instant};
+        let mut src = "define running_total_0 = 0\n".to_string();
+        let mut i = 1;
+        while src.len() < 8 << 20 {
+            src.push_str(&format!("define running_total_{} = -(running_total_{}, previous_offset)\n", i, i - 1));
+            i += 1;
+        }
+        src.push_str(&format!("running_total_{}", i - 1));
+        // the fastest of five runs of f, and what the last returned
+        fn best_of<T, F: Fn() -> T>(f: F) -> (Duration, T) {
+            let mut best = Duration::from_secs(3600);
+            let mut result = f();
+            for _ in 0..5 {
+                let start = Instant::now();
+                result = f();
+                best = best.min(start.elapsed());
+            }
+            (best, result)
+        }
+        let copy = || Lexer::copying_over(&src).collect::<Result<Vec<Token>, LexErr>>().unwrap();
+        let (lex_borrowed, n) = best_of(|| tokenize(&src).unwrap().len());
+        let (lex_copied, m) = best_of(|| copy().len());
+        assert_eq!(n, m);
+        let (borrowed, pgm) = best_of(|| Parser::parse(tokenize(&src).unwrap().into_iter()));
+        let (copied, copied_pgm) = best_of(|| Parser::parse(copy().into_iter()));
+        assert!(pgm.1.is_empty() && copied_pgm.1.is_empty());
+        assert_eq!(pgm.0.defs.len(), i);
+        assert_eq!(pgm.0.to_string(), copied_pgm.0.to_string());
+        println!("{} MB, {} tokens: lexing borrowed {:?}, copied {:?}; lexing and parsing borrowed {:?}, copied {:?}",
+                 src.len() >> 20, n, lex_borrowed, lex_copied, borrowed, copied);
     }
 }
@@ -1,42 +1,164 @@
 // Adapted from file lexer.rs at:
 // https://github.com/kenpratt/rusty_scheme
 
-use std::str;
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fmt;
-use std::iter;
+use std::io;
+use std::io::Read;
+use std::str;
+use unicode_xid::UnicodeXID;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
 
 // defines: Token, LexErr, and Lexer
 
-// if successful, returns Ok(Vec<Token>)
+// if successful, returns Ok(Vec<Token>), whose identifiers are slices of s
 // else returns Err(LexErr)
-pub fn tokenize(s: &str) -> Result<Vec<Token>, LexErr> {
-    Lexer::tokenize(s)
+pub fn tokenize<'src>(s: &'src str) -> Result<Vec<Token<'src>>, LexErr> {
+    Lexer::over(s, false).collect()
+}
+
+// every token that could be scanned, with an Error token in place of each
+// run of bad chars, and every LexErr, in order
+pub fn tokenize_recovering<'src>(s: &'src str) -> (Vec<Token<'src>>, Vec<LexErr>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for result in Lexer::over(s, true) {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(err)  => errors.push(err),
+        }
+    }
+    (tokens, errors)
+}
+
+// a token and the source text it was scanned from; 'src is the lifetime of
+// that text, when the token borrows from it
+#[derive(Clone, PartialEq, Debug)]
+pub struct Token<'src> {
+    pub kind: TokenKind<'src>,
+    pub span: Span,
+}
+
+// the byte range start..end of some source text, and the line and column of
+// its first character and of the position just past its last; lines and
+// columns count from 1, columns both in chars and, as editors speaking LSP
+// expect, in UTF-16 code units
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
+    pub utf16_column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+    pub end_utf16_column: u32,
 }
 
 // PartialEq trait obeys symmetry, transitivity, but not reflexivity, e.g., NAN != NAN
-// PartialEq trait needed for == test on tokens in line 159 of let_lang_parser.rs
+// PartialEq trait needed for == test on tokens in let_lang_parser.rs
 #[derive(Clone, PartialEq, Debug)]
-pub enum Token {
+pub enum TokenKind<'src> {
     Lparen,  // (
     Rparen,  // )
     Comma,   // ,
     Minus,   // -
     Assign,  // =
+    Lbrace,  // {
+    Rbrace,  // }
+    Colon,   // :
+    Semicolon, // ;
+    Lbracket, // [
+    Rbracket, // ]
+    Arrow,   // ->
     IsZero,
     If,
     Then,
     Else,
     Let,
     In,
-    Identifier(String),
+    Define,
+    Begin,
+    End,
+    While,
+    Do,
+    Set,
+    Proc,
+    Module,
+    Interface,
+    Body,
+    From,
+    Take,
+    Opaque,
+    Transparent,
+    TypeKw,  // type
+    Class,
+    Extends,
+    Field,
+    Method,
+    New,
+    Send,
+    Super,
+    SelfKw,  // self
+    Implements,
+    Cast,
+    InstanceOf,
+    Perform,
+    Handle,
+    With,
+    Generator,
+    Yield,
+    Next,
+    IsDone,  // done?
+    Amb,
+    Require,
+    CollectAll,  // collect-all
+    Flip,
+    Observe,
+    Quote,
+    Splice,
+    Run,
+    Assert,
+    DictGet,
+    DictSet,
+    DictHas,
+    DictRemove,
+    DictKeys,
+    Identifier(Cow<'src, str>),  // borrowed from the source unless read or normalized
     Integer(i32),
-    Boolean(bool)
+    Boolean(bool),
+    Str(String),
+    Error,   // chars that could not be scanned, in recovering mode
 }
 
+// the reserved words and the tokens they stand for
+const KEYWORDS: &'static [(&'static str, TokenKind<'static>)] = &[
+    ("iszero", TokenKind::IsZero), ("minus", TokenKind::Minus), ("if", TokenKind::If),
+    ("then", TokenKind::Then), ("else", TokenKind::Else), ("let", TokenKind::Let), ("in", TokenKind::In),
+    ("define", TokenKind::Define), ("begin", TokenKind::Begin), ("end", TokenKind::End),
+    ("while", TokenKind::While), ("do", TokenKind::Do), ("set", TokenKind::Set), ("proc", TokenKind::Proc),
+    ("module", TokenKind::Module), ("interface", TokenKind::Interface), ("body", TokenKind::Body),
+    ("from", TokenKind::From), ("take", TokenKind::Take), ("opaque", TokenKind::Opaque),
+    ("transparent", TokenKind::Transparent), ("type", TokenKind::TypeKw), ("class", TokenKind::Class),
+    ("extends", TokenKind::Extends), ("field", TokenKind::Field), ("method", TokenKind::Method),
+    ("new", TokenKind::New), ("send", TokenKind::Send), ("super", TokenKind::Super),
+    ("self", TokenKind::SelfKw), ("implements", TokenKind::Implements), ("cast", TokenKind::Cast),
+    ("instanceof", TokenKind::InstanceOf), ("perform", TokenKind::Perform), ("handle", TokenKind::Handle),
+    ("with", TokenKind::With), ("generator", TokenKind::Generator), ("yield", TokenKind::Yield),
+    ("next", TokenKind::Next), ("amb", TokenKind::Amb), ("require", TokenKind::Require),
+    ("flip", TokenKind::Flip), ("observe", TokenKind::Observe), ("quote", TokenKind::Quote),
+    ("splice", TokenKind::Splice), ("run", TokenKind::Run), ("assert", TokenKind::Assert),
+    ("true", TokenKind::Boolean(true)), ("false", TokenKind::Boolean(false)), ("done?", TokenKind::IsDone),
+    ("collect-all", TokenKind::CollectAll), ("dict-get", TokenKind::DictGet), ("dict-set", TokenKind::DictSet),
+    ("dict-has?", TokenKind::DictHas), ("dict-remove", TokenKind::DictRemove), ("dict-keys", TokenKind::DictKeys),
+];
+
 pub struct LexErr { // able to store line and column # of error
-    message: String,
-    line: u32,
-    column: u32,
+    pub message: String,
+    pub line: u32,
+    pub column: u32,
+    pub utf16_column: u32,  // for editors, which count in UTF-16 code units
 }
 
 impl fmt::Display for LexErr {
@@ -50,251 +172,866 @@ impl fmt::Debug for LexErr {
     }
 }
 
+impl Span {
+    // from the start of this span to the end of other
+    pub fn to(&self, other: &Span) -> Span {
+        Span { end: other.end, end_line: other.end_line, end_column: other.end_column,
+               end_utf16_column: other.end_utf16_column, ..*self }
+    }
+    // the empty span at a position, as start.. is
+    pub fn at(start: usize, line: u32, column: u32, utf16_column: u32) -> Span {
+        Span { start: start, end: start, line: line, column: column, utf16_column: utf16_column,
+               end_line: line, end_column: column, end_utf16_column: utf16_column }
+    }
+}
+
+impl<'src> Token<'src> {
+    // the empty span just past the token, where the end of input is reported
+    pub fn end_span(&self) -> Span {
+        let span = &self.span;
+        Span::at(span.end, span.end_line, span.end_column, span.end_utf16_column)
+    }
+}
+
+impl<'src> TokenKind<'src> {
+    // how the token is named in messages
+    pub fn describe(&self) -> String {
+        let punct = match *self {
+                        TokenKind::Lparen    => "(",
+                        TokenKind::Rparen    => ")",
+                        TokenKind::Comma     => ",",
+                        TokenKind::Minus     => "-",
+                        TokenKind::Assign    => "=",
+                        TokenKind::Lbrace    => "{",
+                        TokenKind::Rbrace    => "}",
+                        TokenKind::Colon     => ":",
+                        TokenKind::Semicolon => ";",
+                        TokenKind::Lbracket  => "[",
+                        TokenKind::Rbracket  => "]",
+                        TokenKind::Arrow     => "->",
+                        TokenKind::Identifier(ref s) => return format!("identifier `{}`", s),
+                        TokenKind::Integer(i)        => return format!("integer `{}`", i),
+                        TokenKind::Str(ref s)        => return format!("string {:?}", s),
+                        TokenKind::Error             => return "invalid input".to_string(),
+                        _                    => "",
+                        };
+        if !punct.is_empty() {
+            return format!("`{}`", punct);
+        }
+        match KEYWORDS.iter().find(|&&(_, ref kind)| kind == self) {
+            Some(&(word, _)) => format!("`{}`", word),
+            None             => format!("{:?}", self),
+        }
+    }
+}
+
+impl Span {
+    // the line of src the span starts on, numbered, with the span underlined
+    // by carets; a span running past the end of the line is cut off there
+    pub fn excerpt(&self, src: &str) -> String {
+        let line_start = src[..self.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = src[self.start..].find('\n').map(|i| self.start + i).unwrap_or(src.len());
+        let text = &src[line_start..line_end];
+        let indent = src[line_start..self.start].chars().count();
+        let width = src[self.start..self.end.min(line_end).max(self.start)].chars().count().max(1);
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        format!("{} |\n{} | {}\n{} | {}{}", gutter, number, text, gutter, " ".repeat(indent), "^".repeat(width))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line: {}, column: {}", self.line, self.column)
+    }
+}
+
 macro_rules! lex_error {
     ($lexer:ident, $($arg:tt)*) => (
-        return Err(LexErr { message: format!($($arg)*), line: $lexer.line, column: $lexer.column })
+        return Err(LexErr { message: format!($($arg)*), line: $lexer.line, column: $lexer.column,
+                            utf16_column: $lexer.utf16_column })
     )
 }
 
-struct Lexer<'a> {
-    chars: iter::Peekable<str::Chars<'a>>,
+// the chars of a reader, decoded from UTF-8 as they are needed; a read
+// error or bad UTF-8 ends them, and is kept in error
+struct ReadChars<R> {
+    bytes: io::Bytes<io::BufReader<R>>,
+    peeked: Option<Option<char>>,
+    error: Option<String>,
+}
+
+impl<R: Read> ReadChars<R> {
+    fn next(&mut self) -> Option<char> {
+        match self.peeked.take() {
+            Some(c) => c,
+            None    => self.decode(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.decode());
+        }
+        self.peeked.unwrap()
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        match self.bytes.next() {
+            Some(Ok(b))  => Some(b),
+            Some(Err(e)) => { self.error = Some(format!("Could not read the source: {}", e));
+                              None },
+            None         => None,
+        }
+    }
+
+    fn decode(&mut self) -> Option<char> {
+        if self.error.is_some() {
+            return None;
+        }
+        let first = self.byte()?;
+        let len = match first {
+                      0x00 ..= 0x7f => 1,
+                      0xc0 ..= 0xdf => 2,
+                      0xe0 ..= 0xef => 3,
+                      0xf0 ..= 0xf7 => 4,
+                      _             => 0,
+                  };
+        let mut buf = [first, 0, 0, 0];
+        for i in 1..len {
+            match self.byte() {
+                Some(b) => buf[i] = b,
+                None    => break,
+            }
+        }
+        match str::from_utf8(&buf[..len]) {
+            Ok(s) if len > 0 => s.chars().next(),
+            _                => { if self.error.is_none() {
+                                      self.error = Some("Invalid UTF-8 in the source".to_string());
+                                  }
+                                  None },
+        }
+    }
+}
+
+// where the lexer gets its chars: text in memory, which its tokens can
+// borrow from, or a reader, whose text they must copy
+enum Source<'src, R> {
+    Text(&'src str, str::Chars<'src>),
+    Reader(ReadChars<R>),
+}
+
+impl<'src, R: Read> Source<'src, R> {
+    fn next(&mut self) -> Option<char> {
+        match *self {
+            Source::Text(_, ref mut chars) => chars.next(),
+            Source::Reader(ref mut chars)  => chars.next(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        match *self {
+            Source::Text(_, ref chars)    => chars.clone().next(),
+            Source::Reader(ref mut chars) => chars.peek(),
+        }
+    }
+
+    // the text from byte start to byte end, if it is there to be borrowed
+    fn slice(&self, start: usize, end: usize) -> Option<&'src str> {
+        match *self {
+            Source::Text(text, _) => Some(&text[start..end]),
+            Source::Reader(_)     => None,
+        }
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        match *self {
+            Source::Text(..)              => None,
+            Source::Reader(ref mut chars) => chars.error.take(),
+        }
+    }
+}
+
+// scans text a token at a time, reading only as far as it needs to. It
+// stops after an error, unless it is recovering, in which case it goes on
+// with an Error token for the bad chars
+pub struct Lexer<'src, R> {
+    chars: Source<'src, R>,
     current: Option<char>,  // either Some(char) or None
-    tokens: Vec<Token>,
+    pending: VecDeque<Result<Token<'src>, LexErr>>,  // scanned but not yet returned
+    done: bool,
+    pos: usize,     // byte offset of the current char
     line: u32,
     column: u32,
+    utf16_column: u32,
+    recovering: bool,
+    copying: bool,  // identifiers copy their text, which only a reader's must
+}
+
+impl<'src, R: Read> Iterator for Lexer<'src, R> {
+    type Item = Result<Token<'src>, LexErr>;
+
+    fn next(&mut self) -> Option<Result<Token<'src>, LexErr>> {
+        while self.pending.is_empty() && !self.done {
+            match self.current() { // if eof char stream, stop; else process char
+                Some(c) => self.scan(c),
+                None    => {
+                    self.done = true;
+                    if let Some(message) = self.chars.take_error() {
+                        let err = self.error_at(self.start_span(), message);
+                        self.pending.push_back(Err(err));
+                    }
+                },
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+impl<'src> Lexer<'src, io::Empty> {
+    // a lexer over text in memory, whose tokens borrow from it
+    fn over(text: &'src str, recovering: bool) -> Lexer<'src, io::Empty> {
+        Lexer::start(Source::Text(text, text.chars()), recovering)
+    }
+
+    // as over, but copying identifiers, to measure what borrowing saves
+    #[cfg(test)]
+    pub fn copying_over(text: &'src str) -> Lexer<'src, io::Empty> {
+        Lexer { copying: true, ..Lexer::over(text, false) }
+    }
 }
 
-impl<'a> Lexer<'a> {
-    fn tokenize(s: &str) -> Result<Vec<Token>, LexErr> {
-        let mut lexer = Lexer { chars: s.chars().peekable(),   // creates lexer object
+impl<R: Read> Lexer<'static, R> {
+    pub fn new(reader: R) -> Lexer<'static, R> {
+        let chars = ReadChars { bytes: io::BufReader::new(reader).bytes(), peeked: None, error: None };
+        Lexer::start(Source::Reader(chars), false)
+    }
+
+    pub fn recovering(reader: R) -> Lexer<'static, R> {
+        Lexer { recovering: true, ..Lexer::new(reader) }
+    }
+}
+
+impl<'src, R: Read> Lexer<'src, R> {
+    fn start(chars: Source<'src, R>, recovering: bool) -> Lexer<'src, R> {
+        let copying = match chars { Source::Reader(_) => true, Source::Text(..) => false };
+        let mut lexer = Lexer { chars: chars,
                                 current: None,
-                                tokens: Vec::new(),
+                                pending: VecDeque::new(),
+                                done: false,
+                                pos: 0,
                                 line: 1,
-                                column: 0 };
-        try!(lexer.scan()); // May return from tokenize() w/ Err(LexErr). If no error, then
-                            // scan characters and update lexer tokens.
-        Ok(lexer.tokens)    // Return Ok(Vec<Token>)
+                                column: 0,
+                                utf16_column: 0,
+                                recovering: recovering,
+                                copying: copying };
+        lexer.advance(); // set current char to first in char stream
+        lexer
     }
 
     fn current(&self) -> Option<char> {  // pure selector
         self.current}
 
-    fn advance(&mut self) {  // invokes next(), keeps track of line, col.
+    fn advance(&mut self) {  // invokes next(), keeps track of line, col. and byte offset
+        if let Some(c) = self.current() {
+            self.pos += c.len_utf8();
+        }
         if self.current() == Some('\x0a') {
             self.line += 1;
             self.column = 1;
+            self.utf16_column = 1;
         } else {
             self.column += 1;
+            self.utf16_column += self.current().map_or(1, |c| c.len_utf16() as u32);
         }
         self.current = self.chars.next();
     }
 
+    // an empty span at the current char, where a token starts
+    fn start_span(&self) -> Span {
+        Span::at(self.pos, self.line, self.column, self.utf16_column)
+    }
+
+    // adds a token that started at start and ends before the current char
+    fn push(&mut self, kind: TokenKind<'src>, start: Span) {
+        let span = start.to(&self.start_span());
+        self.pending.push_back(Ok(Token { kind: kind, span: span }));
+    }
+
+    // adds a token made of the current char alone, and advances
+    fn push_char(&mut self, kind: TokenKind<'src>) {
+        let start = self.start_span();
+        self.advance();
+        self.push(kind, start);
+    }
+
     fn peek(&mut self) -> Option<char> { // peeks next char
-        match self.chars.peek() {
-            Some(c) => Some(*c),
-            None => None
-        }}
+        self.chars.peek()
+    }
 
-    fn scan(&mut self) -> Result<(), LexErr> {
-        self.advance(); // set current char to first in char stream
-        loop {
-            match self.current() { // if eof char stream, break; else process char
-                Some(c) => {
-                    match c {
-                        _ if c.is_whitespace() => { // interesting construct
-                            self.advance();         // skip over whitespace
-                        },
-                        '(' => {
-                            self.tokens.push(Token::Lparen); // add to token vec
-                            self.advance();                 // and advance
-                        },
-                        ')' => {
-                            self.tokens.push(Token::Rparen); // add to token vec
-                            self.advance();                  // and advance
-                        },
-                        ',' => {
-                            self.tokens.push(Token::Comma); // add to token vec
-                            self.advance();                  // and advance
-                        },
-                        '=' => {
-                            self.tokens.push(Token::Assign); // add to token vec
-                            self.advance();                  // and advance
-                        },
-                        'a' ... 'z' | 'A' ... 'Z' => {
-                            let tok = try!(self.scan_keywrd_ident_bool());
-                            self.tokens.push(tok);
-                            try!(self.parse_whitespace_paren_or_eoi());
-                        }
-                        '-' => {
-                            match self.peek() {
-                                Some('0'...'9') => {
-                                    // skip past the +/- symbol and parse the number
-                                    self.advance();
-                                    let val = try!(self.parse_number());
-                                    self.tokens.push(Token::Integer(if c == '-' { -1 * val } else { val }));
-                                    try!(self.parse_whitespace_paren_or_eoi());
-                                },
-                                _ => {
-                                    // not followed by a digit, must be minus operator
-                                    self.tokens.push(Token::Minus); // add to token vec
-                                    self.advance();
-                                }
-                            }
-                        },
-                        '+' => {
-                            match self.peek() {
-                                Some('0'...'9') => {
-                                    // skip past the +/- symbol and parse the number
-                                    self.advance();
-                                    let val = try!(self.parse_number());
-                                    self.tokens.push(Token::Integer(val));
-                                    try!(self.parse_whitespace_paren_or_eoi());
-                                },
-                                _ => {
-                                    // not followed by a digit
-                                    lex_error!(self, "Isolated plus: {}", c);
-                                }
-                            }
-                        },
-                        '0' ... '9' => {
-                            let val = try!(self.parse_number());
-                            self.tokens.push(Token::Integer(val));
-                            try!(self.parse_whitespace_paren_or_eoi());
-                        }
-                        _ => {
-                            lex_error!(self, "Unexpected character: {}", c);
-                        },
+    // scans the token starting with c. In recovering mode, an error ends
+    // the token, and the bad chars from there up to whatever could start the
+    // next token become an Error token
+    fn scan(&mut self, c: char) {
+        let start = self.start_span();
+        let pushed = self.pending.len();
+        if let Err(err) = self.scan_token(c, start) {
+            self.pending.push_back(Err(err));
+            if !self.recovering {
+                self.done = true;
+                return;
+            }
+            // a literal already pushed is good, and so is the token it ran into
+            if self.pending.len() == pushed + 1 {
+                self.skip_bad_run(start);
+            }
+        }
+    }
+
+    fn scan_token(&mut self, c: char, start: Span) -> Result<(), LexErr> {
+        match c {
+            _ if c.is_whitespace() => { // interesting construct
+                self.advance();         // skip over whitespace
+            },
+            '(' => {
+                self.push_char(TokenKind::Lparen); // add to token vec
+            },
+            ')' => {
+                self.push_char(TokenKind::Rparen); // add to token vec
+            },
+            ',' => {
+                self.push_char(TokenKind::Comma); // add to token vec
+            },
+            '=' => {
+                self.push_char(TokenKind::Assign); // add to token vec
+            },
+            '{' => {
+                self.push_char(TokenKind::Lbrace); // add to token vec
+            },
+            '}' => {
+                self.push_char(TokenKind::Rbrace); // add to token vec
+            },
+            ':' => {
+                self.push_char(TokenKind::Colon); // add to token vec
+            },
+            ';' => {
+                self.push_char(TokenKind::Semicolon); // add to token vec
+            },
+            '[' => {
+                self.push_char(TokenKind::Lbracket); // add to token vec
+            },
+            ']' => {
+                self.push_char(TokenKind::Rbracket); // add to token vec
+            },
+            '%' => { // comment to the end of the line
+                while self.current().map_or(false, |c| c != '\n') {
+                    self.advance();
+                }
+            },
+            '/' if self.peek() == Some('*') => {
+                self.skip_block_comment(start)?;
+            },
+            '"' => {
+                let tok = self.scan_string()?;
+                self.push(tok, start);
+                self.check_literal_end()?;
+            },
+            _ if is_ident_start(c) => {
+                let tok = self.scan_keywrd_ident_bool();
+                self.push(tok, start);
+            }
+            '-' => {
+                match self.peek() {
+                    Some('0'..='9') => {
+                        // skip past the +/- symbol and parse the number
+                        self.advance();
+                        let val = self.parse_number(true, start)?;
+                        self.push(TokenKind::Integer(val), start);
+                        self.check_literal_end()?;
+                    },
+                    Some('>') => {
+                        // arrow of a procedure type or handler clause
+                        self.advance();
+                        self.advance();
+                        self.push(TokenKind::Arrow, start);
+                    },
+                    _ => {
+                        // not followed by a digit, must be minus operator
+                        self.push_char(TokenKind::Minus); // add to token vec
                     }
-                },
-                None => break
+                }
+            },
+            '+' => {
+                match self.peek() {
+                    Some('0'..='9') => {
+                        // skip past the +/- symbol and parse the number
+                        self.advance();
+                        let val = self.parse_number(false, start)?;
+                        self.push(TokenKind::Integer(val), start);
+                        self.check_literal_end()?;
+                    },
+                    _ => {
+                        // not followed by a digit
+                        lex_error!(self, "Isolated plus: {}", c);
+                    }
+                }
+            },
+            '0' ..= '9' => {
+                let val = self.parse_number(false, start)?;
+                self.push(TokenKind::Integer(val), start);
+                self.check_literal_end()?;
             }
-        };
+            _ => {
+                lex_error!(self, "Unexpected character: {}", c);
+            },
+        }
         Ok(())
     }
-    // scan keyword, identifier, or boolean
-    fn scan_keywrd_ident_bool(&mut self) -> Result<Token, LexErr> {
-        let mut s = String::new();  // datatype placed on the heap
+
+    // skips from start, taking at least one char, to the next whitespace or
+    // char that could start a token, and pushes an Error token for what was
+    // skipped
+    fn skip_bad_run(&mut self, start: Span) {
+        if self.pos == start.start {
+            self.advance();
+        }
+        while let Some(c) = self.current() {
+            match c {
+                '(' | ')' | '[' | ']' | '{' | '}' | ',' | ';' | ':' | '=' | '"' | '%' | '-' | '+' => break,
+                '/' if self.peek() == Some('*')                                                   => break,
+                _ if c.is_whitespace() || is_ident_start(c) || c.is_digit(10)                     => break,
+                _                                                                                 => self.advance(),
+            }
+        }
+        self.push(TokenKind::Error, start);
+    }
+    // skips a /* ... */ comment, which may contain others; current char is
+    // the opening '/', at start, where an unterminated comment is reported
+    fn skip_block_comment(&mut self, start: Span) -> Result<(), LexErr> {
+        let mut depth = 0;
         loop {
-            match self.current() {
-                Some(c) => {
-                    match c {
-                        'a' ... 'z' => { s.push(c);
-                                         self.advance();}
-                        'A' ... 'Z' => { s.push(c);
-                                         self.advance();}
-                        _ => {
-                            break;
-                        },
+            match (self.current(), self.peek()) {
+                (Some('/'), Some('*')) => {
+                    depth += 1;
+                    self.advance();
+                    self.advance();
+                },
+                (Some('*'), Some('/')) => {
+                    depth -= 1;
+                    self.advance();
+                    self.advance();
+                    if depth == 0 {
+                        return Ok(());
                     }
                 },
-                None => break
+                (Some(_), _)           => self.advance(),
+                (None, _)              => return Err(self.error_at(start, "Unterminated comment".to_string())),
             }
         }
-        if &s[..] == "iszero" {
-            Ok(Token::IsZero)
-        } else
-           if &s[..] == "minus" {
-            Ok(Token::Minus)
-        } else
-           if &s[..] == "if" {
-            Ok(Token::If)
-        } else
-           if &s[..] == "then" {
-            Ok(Token::Then)
-        } else
-           if &s[..] == "else" {
-            Ok(Token::Else)
-        } else
-           if &s[..] == "let" {
-            Ok(Token::Let)
-        } else
-           if &s[..] == "in" {
-            Ok(Token::In)
-        } else
-           if &s[..] == "true" {
-            Ok(Token::Boolean(true))
-        } else
-           if &s[..] == "false" {
-            Ok(Token::Boolean(false))
-        } else {
-            Ok(Token::Identifier(s))
+    }
+    // scan keyword, identifier, or boolean: the longest run of identifier
+    // chars, short of the '-' of an arrow, in NFC so that an identifier is
+    // the same whether typed with precomposed or combining accents. The
+    // text is borrowed from the source when it is in memory and already in
+    // NFC, which is nearly always
+    fn scan_keywrd_ident_bool(&mut self) -> TokenKind<'src> {
+        let start = self.pos;
+        let copying = self.copying;
+        let mut copy = String::new();  // the text, when it cannot be borrowed
+        loop {
+            match self.current() {
+                Some('-') if self.peek() == Some('>') => break,
+                Some(c) if is_ident_char(c)           => { if copying { copy.push(c); }
+                                                           self.advance();}
+                _                                     => break,
+            }
+        }
+        let s = match self.chars.slice(start, self.pos) {
+                    Some(text) if !copying => Cow::Borrowed(text),
+                    _                      => Cow::Owned(copy),
+                };
+        let s = if is_nfc(&s) { s } else { Cow::Owned(s.nfc().collect()) };
+        match KEYWORDS.iter().find(|&&(word, _)| word == &s[..]) {
+            Some(&(_, ref kind)) => kind.clone(),
+            None                 => TokenKind::Identifier(s),
         }
     }
 
-    fn parse_number(&mut self) -> Result<i32, LexErr> {
+    // scan a string literal; current char is the opening quote
+    fn scan_string(&mut self) -> Result<TokenKind<'src>, LexErr> {
         let mut s = String::new();
+        self.advance();  // skip opening quote
         loop {
             match self.current() {
-                Some(c) => {
-                    match c {
-                        '0'...'9' => {
-                            s.push(c);
-                            self.advance();
-                        },
-                        _ => break
-                    }
-                },
-                None => break
+                Some('"')  => { self.advance();
+                                break;}
+                Some('\\') => { self.advance();
+                                match self.current() {
+                                    Some('n')  => s.push('\n'),
+                                    Some('"')  => s.push('"'),
+                                    Some('\\') => s.push('\\'),
+                                    Some(c)    => lex_error!(self, "Unknown escape in string: \\{}", c),
+                                    None       => lex_error!(self, "Unterminated string literal"),
+                                }
+                                self.advance();}
+                Some(c)    => { s.push(c);
+                                self.advance();}
+                None       => lex_error!(self, "Unterminated string literal"),
+            }
+        }
+        Ok(TokenKind::Str(s))
+    }
+
+    // an integer literal, decimal, or hex after 0x or binary after 0b, whose
+    // digits may be separated by '_'; the sign, already skipped, is applied
+    // before the range check so that i32::MIN can be written. Errors are
+    // reported at start, where the literal begins
+    fn parse_number(&mut self, negative: bool, start: Span) -> Result<i32, LexErr> {
+        let mut radix = 10;
+        if self.current() == Some('0') {
+            match self.peek() {
+                Some('x') | Some('X') => radix = 16,
+                Some('b') | Some('B') => radix = 2,
+                _                     => (),
+            }
+            if radix != 10 {
+                self.advance();
+                self.advance();
+            }
+        }
+        let mut digits = String::new();
+        if negative {
+            digits.push('-');
+        }
+        let mut text = String::new();
+        loop {
+            match self.current() {
+                Some(c) if c.is_digit(radix) => { digits.push(c);
+                                                  text.push(c);
+                                                  self.advance();}
+                Some('_') if !text.is_empty() => { text.push('_');
+                                                   self.advance();}
+                _                             => break,
             }
         }
-        match s.parse::<i32>() {
+        let sign = if negative { "-" } else { "" };
+        let prefix = match radix { 16 => "0x", 2 => "0b", _ => "" };
+        if text.is_empty() || text.ends_with('_') {
+            return Err(self.error_at(start, format!("Malformed integer literal: {}{}{}", sign, prefix, text)));
+        }
+        match i32::from_str_radix(&digits, radix) {
             Ok(value) => Ok(value),
-            Err(_) => { lex_error!(self, "Not a number: {}", self.current().unwrap()); },
+            Err(_)    => Err(self.error_at(start, format!("Integer literal out of range: {}{}{}", sign, prefix, text))),
         }
     }
 
-    fn parse_whitespace_paren_or_eoi(&mut self) -> Result<(), LexErr> {
+    // an error located at start, rather than at the current char
+    fn error_at(&self, start: Span, message: String) -> LexErr {
+        LexErr { message: message, line: start.line, column: start.column, utf16_column: start.utf16_column }
+    }
+
+    // a number or string may be followed by anything but the start of
+    // another word, number or string, which would run into it
+    fn check_literal_end(&mut self) -> Result<(), LexErr> {
         match self.current() {
-            Some(c) => {
-                match c {
-                    _ if c.is_whitespace() => (),
-                    '(' => {
-                        self.tokens.push(Token::Lparen);
-                        self.advance();
-                    },
-                    ')' => {
-                        self.tokens.push(Token::Rparen);
-                        self.advance();
-                    },
-                    ',' => {
-                        self.tokens.push(Token::Comma);
-                        self.advance();
-                    },
-                    _ => lex_error!(self, "Unexpected char, expected whitespace: {}", c),
-                }
-            },
-            None => ()
-        };
-        Ok(())
+            Some(c) if is_ident_char(c) || c == '"' => lex_error!(self, "Unexpected character after literal: {}", c),
+            _                                       => Ok(()),
+        }
     }}
 
+// identifiers follow the Unicode XID rules: they start with a letter, of
+// any script, or '_', and go on with letters, digits, combining marks and
+// '_', and also - ? ! as in is-even? and set!
+fn is_ident_start(c: char) -> bool {
+    UnicodeXID::is_xid_start(c) || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    UnicodeXID::is_xid_continue(c) || c == '-' || c == '?' || c == '!'
+}
+
+#[cfg(test)]
+fn kinds<'a>(s: &'a str) -> Vec<TokenKind<'a>> {
+    tokenize(s).unwrap().into_iter().map(|t| t.kind).collect()
+}
+
 #[test]
 fn subtraction_and_multi_digit_integers() {
-    assert_eq!(tokenize("-(24, +31)").unwrap(),
-               vec![Token::Minus, Token::Lparen, Token::Integer(24), 
-                    Token::Comma, Token::Integer(31), Token::Rparen]);
+    assert_eq!(kinds("-(24, +31)"),
+               vec![TokenKind::Minus, TokenKind::Lparen, TokenKind::Integer(24), 
+                    TokenKind::Comma, TokenKind::Integer(31), TokenKind::Rparen]);
 }
 
 #[test]
 fn if_expression_and_boolean() {
-    assert_eq!(tokenize("if true then 1 else -1").unwrap(),
-               vec![Token::If, Token::Boolean(true), 
-                    Token::Then, Token::Integer(1), 
-                    Token::Else, Token::Integer(-1)]);
+    assert_eq!(kinds("if true then 1 else -1"),
+               vec![TokenKind::If, TokenKind::Boolean(true), 
+                    TokenKind::Then, TokenKind::Integer(1), 
+                    TokenKind::Else, TokenKind::Integer(-1)]);
 }
 
 #[test]
 fn let_expression_and_assignment() {
-    assert_eq!(tokenize("let temp = 3 in -(temp, 103)").unwrap(),
-               vec![Token::Let, Token::Identifier("temp".to_string()), Token::Assign, Token::Integer(3),
-               Token::In, Token::Minus, Token::Lparen, Token::Identifier("temp".to_string()),
-               Token::Comma, Token::Integer(103), Token::Rparen]);
+    assert_eq!(kinds("let temp = 3 in -(temp, 103)"),
+               vec![TokenKind::Let, ident("temp"), TokenKind::Assign, TokenKind::Integer(3),
+               TokenKind::In, TokenKind::Minus, TokenKind::Lparen, ident("temp"),
+               TokenKind::Comma, TokenKind::Integer(103), TokenKind::Rparen]);
 }
 
 #[test]
 fn iszero_let_expression_and_assignment() {
-    assert_eq!(tokenize("if iszero(TextId) then let x = -571 in false").unwrap(),
-               vec![Token::If, Token::IsZero, Token::Lparen, Token::Identifier("TextId".to_string()),
-               Token::Rparen, Token::Then, Token::Let, Token::Identifier("x".to_string()),
-               Token::Assign, Token::Integer(-571), Token::In, Token::Boolean(false)]);
+    assert_eq!(kinds("if iszero(TextId) then let x = -571 in false"),
+               vec![TokenKind::If, TokenKind::IsZero, TokenKind::Lparen, ident("TextId"),
+               TokenKind::Rparen, TokenKind::Then, TokenKind::Let, ident("x"),
+               TokenKind::Assign, TokenKind::Integer(-571), TokenKind::In, TokenKind::Boolean(false)]);
+}
+
+#[test]
+fn dict_literal_and_primitives() {
+    assert_eq!(kinds("dict-has?(dict-set({1: \"one\"}, true, 2), \"a b\")"),
+               vec![TokenKind::DictHas, TokenKind::Lparen, TokenKind::DictSet, TokenKind::Lparen,
+               TokenKind::Lbrace, TokenKind::Integer(1), TokenKind::Colon, TokenKind::Str("one".to_string()),
+               TokenKind::Rbrace, TokenKind::Comma, TokenKind::Boolean(true), TokenKind::Comma, TokenKind::Integer(2),
+               TokenKind::Rparen, TokenKind::Comma, TokenKind::Str("a b".to_string()), TokenKind::Rparen]);
+}
+
+#[test]
+fn begin_sequence_and_semicolons() {
+    assert_eq!(kinds("begin x; -(x, 1);true end"),
+               vec![TokenKind::Begin, ident("x"), TokenKind::Semicolon,
+               TokenKind::Minus, TokenKind::Lparen, ident("x"), TokenKind::Comma,
+               TokenKind::Integer(1), TokenKind::Rparen, TokenKind::Semicolon, TokenKind::Boolean(true), TokenKind::End]);
+}
+
+#[test]
+fn module_interface_and_arrow_types() {
+    assert_eq!(kinds("module m interface [f : (int -> bool)] body [f = proc (x : int) iszero(x)]"),
+               vec![TokenKind::Module, ident("m"), TokenKind::Interface, TokenKind::Lbracket,
+               ident("f"), TokenKind::Colon, TokenKind::Lparen,
+               ident("int"), TokenKind::Arrow, ident("bool"),
+               TokenKind::Rparen, TokenKind::Rbracket, TokenKind::Body, TokenKind::Lbracket,
+               ident("f"), TokenKind::Assign, TokenKind::Proc, TokenKind::Lparen,
+               ident("x"), TokenKind::Colon, ident("int"),
+               TokenKind::Rparen, TokenKind::IsZero, TokenKind::Lparen, ident("x"),
+               TokenKind::Rparen, TokenKind::Rbracket]);
+}
+
+#[test]
+fn typed_class_keywords() {
+    assert_eq!(kinds("class c extends object implements i if instanceof self c then cast self c else self"),
+               vec![TokenKind::Class, ident("c"), TokenKind::Extends,
+               ident("object"), TokenKind::Implements, ident("i"),
+               TokenKind::If, TokenKind::InstanceOf, TokenKind::SelfKw, ident("c"), TokenKind::Then,
+               TokenKind::Cast, TokenKind::SelfKw, ident("c"), TokenKind::Else, TokenKind::SelfKw]);
+}
+
+#[test]
+fn search_and_generator_words() {
+    assert_eq!(kinds("collect-all amb(1, 2) done?(g)"),
+               vec![TokenKind::CollectAll, TokenKind::Amb, TokenKind::Lparen, TokenKind::Integer(1), TokenKind::Comma,
+               TokenKind::Integer(2), TokenKind::Rparen, TokenKind::IsDone, TokenKind::Lparen,
+               ident("g"), TokenKind::Rparen]);
+    assert_eq!(kinds("collect-some"), vec![ident("collect-some")]);
+}
+
+#[test]
+fn predicate_names_end_in_question_marks() {
+    assert_eq!(kinds("let x : positive? = 1 in assert(x, \"ok\")"),
+               vec![TokenKind::Let, ident("x"), TokenKind::Colon,
+               ident("positive?"), TokenKind::Assign, TokenKind::Integer(1), TokenKind::In,
+               TokenKind::Assert, TokenKind::Lparen, ident("x"), TokenKind::Comma,
+               TokenKind::Str("ok".to_string()), TokenKind::Rparen]);
+}
+
+#[test]
+fn tokens_record_their_spans() {
+    let tokens = tokenize("let x =\n  -(x, -10) -> \"é\"").unwrap();
+    let spans: Vec<(usize, usize, u32, u32)> =
+        tokens.iter().map(|t| (t.span.start, t.span.end, t.span.line, t.span.column)).collect();
+    assert_eq!(spans, vec![(0, 3, 1, 1), (4, 5, 1, 5), (6, 7, 1, 7), (10, 11, 2, 3), (11, 12, 2, 4),
+                           (12, 13, 2, 5), (13, 14, 2, 6), (15, 18, 2, 8), (18, 19, 2, 11), (20, 22, 2, 13),
+                           (23, 27, 2, 16)]);
+}
+
+#[test]
+fn line_and_nested_block_comments() {
+    assert_eq!(kinds("% a program\nlet x = 1/* one */in /* a /* nested */ comment\n */ x% done"),
+               vec![TokenKind::Let, ident("x"), TokenKind::Assign,
+                    TokenKind::Integer(1), TokenKind::In, ident("x")]);
+    let tokens = tokenize("/**/ x").unwrap();
+    assert_eq!((tokens[0].span.start, tokens[0].span.column), (5, 6));
+}
+
+#[test]
+fn unterminated_comments_point_at_their_start() {
+    let err = tokenize("let x = 1 in\n  /* a /* nested */ comment\nx").err().unwrap();
+    assert_eq!((&err.message[..], err.line, err.column), ("Unterminated comment", 2, 3));
+}
+
+#[test]
+fn scheme_style_identifiers() {
+    assert_eq!(kinds("is-even? x1 set! _tmp dict-size done? collect-allx"),
+               vec![ident("is-even?"), ident("x1"),
+                    ident("set!"), ident("_tmp"),
+                    ident("dict-size"), TokenKind::IsDone,
+                    ident("collect-allx")]);
+}
+
+#[test]
+fn tokens_may_touch_delimiters_and_operators() {
+    assert_eq!(kinds("let x=-(x1,2)in(int->bool)"),
+               vec![TokenKind::Let, ident("x"), TokenKind::Assign, TokenKind::Minus,
+                    TokenKind::Lparen, ident("x1"), TokenKind::Comma,
+                    TokenKind::Integer(2), TokenKind::Rparen, TokenKind::In, TokenKind::Lparen,
+                    ident("int"), TokenKind::Arrow,
+                    ident("bool"), TokenKind::Rparen]);
+    assert!(tokenize("12ab").is_err());
+    assert!(tokenize("\"a\"\"b\"").is_err());
+}
+
+#[cfg(test)]
+fn ident<'a>(s: &'a str) -> TokenKind<'a> {
+    TokenKind::Identifier(Cow::Borrowed(s))
+}
+
+#[test]
+fn non_latin_identifiers() {
+    assert_eq!(kinds("let λ = 1 in -(λ, имя) π2 名前 قيمة अंक_1 ἀρχή"),
+               vec![TokenKind::Let, ident("λ"), TokenKind::Assign, TokenKind::Integer(1), TokenKind::In,
+                    TokenKind::Minus, TokenKind::Lparen, ident("λ"), TokenKind::Comma, ident("имя"),
+                    TokenKind::Rparen, ident("π2"), ident("名前"), ident("قيمة"), ident("अंक_1"), ident("ἀρχή")]);
+    assert!(tokenize("x ☃").is_err());
+    assert!(tokenize("٣x").is_err());
+}
+
+#[test]
+fn identifiers_are_compared_in_nfc() {
+    assert_eq!(kinds("cafe\u{301} caf\u{e9} A\u{30a}ngstro\u{308}m"),
+               vec![ident("caf\u{e9}"), ident("caf\u{e9}"), ident("\u{c5}ngstr\u{f6}m")]);
+}
+
+#[test]
+fn columns_count_chars_and_utf16_units() {
+    let tokens = tokenize("名前 = 𝑥\n  𝑥 λ").unwrap();
+    let columns: Vec<(u32, u32, u32)> =
+        tokens.iter().map(|t| (t.span.line, t.span.column, t.span.utf16_column)).collect();
+    assert_eq!(columns, vec![(1, 1, 1), (1, 4, 4), (1, 6, 6), (2, 3, 3), (2, 5, 6)]);
+    let err = tokenize("𝑥 ☃").err().unwrap();
+    assert_eq!((err.line, err.column, err.utf16_column), (1, 3, 4));
+}
+
+#[test]
+fn integer_literals_cover_the_i32_range() {
+    assert_eq!(kinds("-2147483648 2147483647 +0 0x7fff_FFFF -0x80000000 0b1010 1_000_000 007"),
+               vec![TokenKind::Integer(i32::min_value()), TokenKind::Integer(i32::max_value()), TokenKind::Integer(0),
+                    TokenKind::Integer(i32::max_value()), TokenKind::Integer(i32::min_value()),
+                    TokenKind::Integer(10), TokenKind::Integer(1000000), TokenKind::Integer(7)]);
+}
+
+#[test]
+fn bad_integer_literals_are_spanned_errors() {
+    let err = tokenize("let x =\n  2147483648").err().unwrap();
+    assert_eq!((&err.message[..], err.line, err.column), ("Integer literal out of range: 2147483648", 2, 3));
+    let err = tokenize("-(1, -0x8000_0001)").err().unwrap();
+    assert_eq!((&err.message[..], err.line, err.column), ("Integer literal out of range: -0x8000_0001", 1, 6));
+    for bad in &["0x", "0b12", "1__", "0xg", "12_"] {
+        assert!(tokenize(bad).is_err(), "{} should not scan", bad);
+    }
+}
+
+#[test]
+fn recovering_mode_reports_every_error() {
+    let (tokens, errors) = tokenize_recovering("let x@y = 12ab in #-(x, 99999999999)\n\"open");
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages, vec!["LexError: Unexpected character: @ (line: 1, column: 6)",
+                              "LexError: Unexpected character after literal: a (line: 1, column: 13)",
+                              "LexError: Unexpected character: # (line: 1, column: 19)",
+                              "LexError: Integer literal out of range: 99999999999 (line: 1, column: 25)",
+                              "LexError: Unterminated string literal (line: 2, column: 6)"]);
+    let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind.clone()).collect();
+    assert_eq!(kinds, vec![TokenKind::Let, ident("x"), TokenKind::Error, ident("y"), TokenKind::Assign,
+                           TokenKind::Integer(12), ident("ab"), TokenKind::In, TokenKind::Error, TokenKind::Minus,
+                           TokenKind::Lparen, ident("x"), TokenKind::Comma, TokenKind::Error, TokenKind::Rparen,
+                           TokenKind::Error]);
+    let spans: Vec<(usize, usize)> = tokens.iter().filter(|t| t.kind == TokenKind::Error)
+                                           .map(|t| (t.span.start, t.span.end)).collect();
+    assert_eq!(spans, vec![(5, 6), (18, 19), (24, 35), (37, 42)]);
+    let (tokens, errors) = tokenize_recovering("a#$+1 ~/* c */b");
+    assert_eq!(errors.len(), 2);
+    let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind.clone()).collect();
+    assert_eq!(kinds, vec![ident("a"), TokenKind::Error, TokenKind::Integer(1), TokenKind::Error, ident("b")]);
+}
+
+#[test]
+fn the_lexer_reads_only_as_far_as_it_needs() {
+    // a reader with no end
+    struct Endless;
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = b"x1 "[i % 3];
+            }
+            Ok(buf.len() / 3 * 3)
+        }
+    }
+    let tokens: Vec<Token> = Lexer::new(Endless).take(10000).map(|t| t.unwrap()).collect();
+    assert!(tokens.iter().all(|t| t.kind == ident("x1")));
+    assert_eq!(tokens[9999].span.start, 29997);
+}
+
+#[test]
+fn chars_may_be_split_across_reads() {
+    // a reader handing over one byte at a time
+    struct Trickle<'a>(&'a [u8]);
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+    let src = "let 名前 = \"𝑥é\" in -(λ, 0x1F)";
+    let streamed: Vec<Token> = Lexer::new(Trickle(src.as_bytes())).map(|t| t.unwrap()).collect();
+    assert_eq!(streamed, tokenize(src).unwrap());
+}
+
+#[test]
+fn read_errors_and_bad_utf8_are_lex_errors() {
+    struct Broken;
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk on fire"))
+        }
+    }
+    let results: Vec<Result<Token, LexErr>> = Lexer::new(Broken).collect();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].as_ref().err().unwrap().message, "Could not read the source: disk on fire");
+    let results: Vec<Result<Token, LexErr>> = Lexer::new(&b"x \xff y"[..]).collect();
+    assert_eq!(results[0].as_ref().ok().unwrap().kind, ident("x"));
+    let err = results[1].as_ref().err().unwrap();
+    assert_eq!((&err.message[..], err.line, err.column), ("Invalid UTF-8 in the source", 1, 3));
+    assert_eq!(results.len(), 2);
+}
+
+#[test]
+fn identifiers_borrow_from_the_source_text() {
+    let src = String::from("let total = -(total, cafe\u{301}) in caf\u{e9}");
+    let tokens = tokenize(&src).unwrap();
+    let texts: Vec<(&str, bool)> = tokens.iter().filter_map(|t| match t.kind {
+                                       TokenKind::Identifier(Cow::Borrowed(s)) => Some((s, true)),
+                                       TokenKind::Identifier(Cow::Owned(ref s)) => Some((&s[..], false)),
+                                       _                                        => None,
+                                   }).collect();
+    // only the identifier that had to be normalized is a copy
+    assert_eq!(texts, vec![("total", true), ("total", true), ("caf\u{e9}", false), ("caf\u{e9}", true)]);
+    if let TokenKind::Identifier(ref s) = tokens[1].kind {
+        assert_eq!(s.as_ptr(), src[4..].as_ptr());
+    }
+    // a reader's text is gone once read, so its tokens own theirs
+    let streamed: Vec<Token<'static>> = Lexer::new(src.as_bytes()).map(|t| t.unwrap()).collect();
+    assert!(streamed.iter().all(|t| match t.kind { TokenKind::Identifier(Cow::Borrowed(_)) => false, _ => true }));
+    assert_eq!(streamed, tokens);
 }
@@ -0,0 +1,113 @@
+// Types for the checker, module interfaces, proc annotations and classes
+
+use std::rc::Rc;
+use std::fmt;
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum Type {
+    IntType,
+    BoolType,
+    StrType,
+    ListType,
+    DictType,
+    GeneratorType,
+    CodeType,
+    AnyType,                          // unknown statically: dict lookups, unannotated parameters
+    VoidType,                         // the result of a method called only for its effect
+    ProcType(Vec<Type>, Rc<Type>),    // (t1, ..., tn -> t)
+    NamedType(String),                // t, declared in the enclosing module, or a class name
+    QualifiedType(String, String),    // from m take t; also the abstract type of an opaque t
+    ClassType(String, Rc<Vec<String>>),  // a class or interface, with every type it is a subtype of
+}
+
+impl Type {
+    pub fn new_proc_type(args: &Vec<Type>, result: &Type) -> Self {
+        Type::ProcType(args.clone(), Rc::new(result.clone()))
+    }
+    // the type written as a bare identifier, if any
+    pub fn from_name(s: &str) -> Option<Type> {
+        match s {
+            "int"       => Some(Type::IntType),
+            "bool"      => Some(Type::BoolType),
+            "string"    => Some(Type::StrType),
+            "list"      => Some(Type::ListType),
+            "dict"      => Some(Type::DictType),
+            "generator" => Some(Type::GeneratorType),
+            "code"      => Some(Type::CodeType),
+            "any"       => Some(Type::AnyType),
+            "void"      => Some(Type::VoidType),
+            _           => None,
+        }}
+    // the type named by an identifier: a built-in type or a module's type name
+    pub fn from_ident(s: &String) -> Type {
+        match Type::from_name(s) {
+            Some(t) => t,
+            None    => Type::NamedType(s.clone()),
+        }}
+    // can a value of type actual be used where expected is required?
+    // AnyType is compatible in both directions, except with an abstract type:
+    // letting those through any would expose the representation of an opaque type.
+    // Any value may be discarded as void; an object may be used as any of its
+    // superclasses or interfaces. A procedure fits if it accepts at least the
+    // arguments expected ones do, and returns what they would
+    pub fn is_compatible(expected: &Type, actual: &Type) -> bool {
+        match (expected, actual) {
+            (&Type::AnyType, &Type::QualifiedType(..)) | (&Type::QualifiedType(..), &Type::AnyType) => false,
+            (&Type::AnyType, _) | (_, &Type::AnyType) | (&Type::VoidType, _) => true,
+            (&Type::ClassType(ref c, _), &Type::ClassType(_, ref supers)) => supers.contains(c),
+            (&Type::ProcType(ref args1, ref res1), &Type::ProcType(ref args2, ref res2)) =>
+                args1.len() == args2.len()
+                && args1.iter().zip(args2.iter()).all(|(t1, t2)| Type::is_compatible(t2, t1))
+                && Type::is_compatible(res1, res2),
+            _ => expected == actual,
+        }}
+    // the type of an expression that may produce either; None if they conflict.
+    // A value of type any may not pass for one of a type mentioning an opaque
+    // type, which could then be forged from its representation
+    pub fn join(t1: &Type, t2: &Type) -> Option<Type> {
+        match (t1, t2) {
+            (&Type::AnyType, t) | (t, &Type::AnyType) if t.mentions_opaque() => return None,
+            _                                                                  => (),
+        }
+        if *t1 == Type::AnyType {
+            Some(t2.clone())
+        } else if Type::is_compatible(t1, t2) {
+            Some(t1.clone())
+        } else if Type::is_compatible(t2, t1) {
+            Some(t2.clone())
+        } else {
+            None
+        }}
+    // does a value of this type hold, or take or return, one of an opaque type?
+    pub fn mentions_opaque(&self) -> bool {
+        match *self {
+            Type::QualifiedType(..)              => true,
+            Type::ProcType(ref args, ref result) => args.iter().any(|t| t.mentions_opaque()) || result.mentions_opaque(),
+            _                                    => false,
+        }}
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::IntType  => write!(f, "int"),
+            Type::BoolType => write!(f, "bool"),
+            Type::StrType  => write!(f, "string"),
+            Type::ListType => write!(f, "list"),
+            Type::DictType => write!(f, "dict"),
+            Type::GeneratorType => write!(f, "generator"),
+            Type::CodeType => write!(f, "code"),
+            Type::AnyType  => write!(f, "any"),
+            Type::VoidType => write!(f, "void"),
+            Type::ProcType(ref args, ref result) => {write!(f, "(")?;
+                                                     for (i, t) in args.iter().enumerate() {
+                                                         if i > 0 { write!(f, ", ")? };
+                                                         write!(f, "{}", t)?;
+                                                     }
+                                                     if !args.is_empty() { write!(f, " ")? };
+                                                     write!(f, "-> {})", result)},
+            Type::NamedType(ref t)           => write!(f, "{}", t),
+            Type::QualifiedType(ref m, ref t) => write!(f, "from {} take {}", m, t),
+            Type::ClassType(ref c, _)        => write!(f, "{}", c),
+        }
+    }}
@@ -0,0 +1,64 @@
+// Exact probabilities for the probabilistic driver: fractions kept in lowest
+// terms. Arithmetic returns None on overflow rather than losing exactness.
+
+use std::fmt;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Weight {
+    pub num: u128,
+    pub den: u128,
+}
+
+impl Weight {
+    pub fn new_weight(num: u128, den: u128) -> Self {
+        let g = gcd(num, den);
+        Weight { num: num / g, den: den / g }
+    }
+    pub fn one() -> Self {
+        Weight { num: 1, den: 1 }
+    }
+    pub fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+    pub fn mul(&self, other: &Weight) -> Option<Weight> {
+        Some(Weight::new_weight(self.num.checked_mul(other.num)?, self.den.checked_mul(other.den)?))
+    }
+    pub fn add(&self, other: &Weight) -> Option<Weight> {
+        let num = self.num.checked_mul(other.den)?.checked_add(other.num.checked_mul(self.den)?)?;
+        Some(Weight::new_weight(num, self.den.checked_mul(other.den)?))
+    }
+    pub fn div(&self, other: &Weight) -> Option<Weight> {
+        if other.is_zero() {
+            return None;
+        }
+        Some(Weight::new_weight(self.num.checked_mul(other.den)?, self.den.checked_mul(other.num)?))
+    }
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        if a == 0 { 1 } else { a }
+    } else {
+        gcd(b, a % b)
+    }}
+
+impl fmt::Display for Weight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }}
+
+#[cfg(test)]
+mod test {
+    use super::Weight;
+
+    #[test]
+    fn fractions_stay_in_lowest_terms() {
+        let third = Weight::new_weight(2, 6);
+        assert_eq!(third, Weight::new_weight(1, 3));
+        assert_eq!(third.add(&third).unwrap(), Weight::new_weight(2, 3));
+        assert_eq!(third.mul(&Weight::new_weight(3, 4)).unwrap().to_string(), "1/4");
+        assert_eq!(third.div(&Weight::new_weight(2, 3)).unwrap().to_string(), "1/2");
+        assert_eq!(Weight::new_weight(0, 5).to_string(), "0/1");
+        assert!(Weight::new_weight(u128::max_value(), 1).mul(&Weight::new_weight(2, 1)).is_none());
+    }
+}
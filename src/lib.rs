@@ -1,8 +1,16 @@
+extern crate unicode_xid;
+extern crate unicode_normalization;
+
 pub mod let_lang_scanner;
 pub mod let_lang_parser;
 pub mod let_lang_exp;
 pub mod let_lang_env;
 pub mod int_bool;
+pub mod let_lang_type;
+pub mod let_lang_checker;
+pub mod let_lang_class;
+pub mod let_lang_weight;
+pub mod let_lang_interp;
 
 // #[test]
 // fn it_works() {
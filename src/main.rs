@@ -5,72 +5,8 @@ use let_lang_proj::let_lang_parser::*;
 use let_lang_proj::let_lang_exp::*;
 use let_lang_proj::let_lang_env::*;
 use let_lang_proj::int_bool::*;
-
-fn value_of(ast: &LetLangExp, env: &LetLangEnv) -> Option<IntBool> { // defined in int_bool.rs
-    match ast.clone() {
-        LetLangExp::ConstExp(int)    => Some(IntBool::Integer(int)),
-        LetLangExp::Boolean(b)       => Some(IntBool::Boolean(b)),
-        LetLangExp::DiffExp(e1, e2)  => value_of_diff_exp(&(*e1), &(*e2), env),
-        LetLangExp::IsZeroExp(e)     => value_of_iszero(&(*e), env),
-        LetLangExp::IfExp(e1,e2,e3)  => value_of_if(&(*e1),&(*e2),&(*e3), env),
-        LetLangExp::VarExp(s)        => env.apply_env(&s),
-        LetLangExp::LetExp(s,e1,e2)  => value_of_let(&s, &(*e1), &(*e2), env),
-    }}
-
-fn value_of_let(s: &String, e1: &LetLangExp, e2: &LetLangExp, env: &LetLangEnv) -> Option<IntBool>{
-    let new_val = value_of(e1, env);
-    if new_val.is_none() { return None};
-    let new_env = env.extend_env(s, new_val.unwrap());
-    value_of(e2, &new_env)
-}
-
-fn value_of_if(e1: &LetLangExp, e2: &LetLangExp, e3: &LetLangExp, env: &LetLangEnv) -> Option<IntBool> {
-    if match value_of(e1, env) { // compute value of test and treat as true only if boolean true
-        Some(x) => match x {
-            IntBool::Integer(_i) => false,
-            IntBool::Boolean(b) => b,
-            },
-        None => false,
-        }
-        {
-            value_of(e2, env)
-        } else {
-            value_of(e3, env)
-        }}
-
-fn value_of_iszero(e: &LetLangExp, env: &LetLangEnv) -> Option<IntBool> {
-    let opt_val = value_of(e, env);
-    match opt_val {
-        Some(x) => match x {
-            IntBool::Integer(i)  => Some(IntBool::Boolean(i == 0)),
-            IntBool::Boolean(_b) => None,
-        },
-        None    => None,
-    }}
-
-// checked difference
-fn value_of_diff_exp(arg1: &LetLangExp, arg2: &LetLangExp, env: &LetLangEnv) -> Option<IntBool> {
-    let val1 = value_of(arg1, env);
-    let val2 = value_of(arg2, env);
-    if val1.is_none() || val2.is_none() {
-        None
-    } else {
-        let v1 = val1.unwrap();
-        let v2 = val2.unwrap();
-        Some(IntBool::Integer(value_of_diff_exp_work(&v1, &v2)))
-    }}
-
-fn value_of_diff_exp_work(a1: &IntBool, a2: &IntBool) -> i32 {
-    let a1_int_val: i32 = match *a1 {
-                            IntBool::Integer(i) => i,
-                            _                   => 0,
-                            };
-    let a2_int_val: i32 = match *a2 {
-                            IntBool::Integer(i) => i,
-                            _                   => 0,
-                            };
-    a1_int_val - a2_int_val
-}
+use let_lang_proj::let_lang_interp::*;
+use let_lang_proj::let_lang_checker::*;
 
 #[allow(dead_code)]
 fn main() {
@@ -146,7 +82,7 @@ fn main() {
     match mile_ast_result {
         Ok(v)  => {println!("{}", v);      // regular print
                    println!("{:#?}", v);   // pretty-print in debug format
-                   println!("\nmilestone = {:?}", value_of(&v, &env));},
+                   println!("\nmilestone = {:?}", value_of_program(&v, &env));},
         Err(e) => println!("Syntax error: {:#?}", e),
     }
 
@@ -169,18 +105,171 @@ fn main() {
     match if_ast_result {
         Ok(v)  => {println!("{}", v);
                    println!("{:#?}", v);
-                   println!("\nif_value = {:?}", value_of(&v, &env2));},
+                   println!("\nif_value = {:?}", value_of_program(&v, &env2));},
+        Err(e) => println!("Syntax error: {:#?}", e),
+    }
+
+    println!("\nStarting to parse: dictionaries");
+    let dict_str =
+    "let d = {1: \"one\", \"two\": 2, true: {}}
+     in if dict-has?(d, \"two\")
+        then dict-keys(dict-remove(dict-set(d, 3, false), 1))
+        else dict-get(d, 1)";
+    let dict_tokens = tokenize(dict_str).unwrap();
+    match parse(&dict_tokens) {
+        Ok(v)  => {println!("{}", v);
+                   println!("\ndict_value = {:?}", value_of_program(&v, &env));},
+        Err(e) => println!("Syntax error: {:#?}", e),
+    }
+
+    println!("\nStarting to parse: definitions");
+    let define_str =
+    "define base = 10
+     define limit = -(base, -5)
+     define table = {base: \"base\", limit: \"limit\"}
+     begin dict-keys(table); dict-get(table, 15) end";
+    let define_tokens = tokenize(define_str).unwrap();
+    match parse(&define_tokens) {
+        Ok(v)  => {println!("{}", v);
+                   println!("\ndefine_value = {:?}", value_of_program(&v, &env));},
+        Err(e) => println!("Syntax error: {:#?}", e),
+    }
+
+    println!("\nStarting to parse: loops");
+    let loop_str =
+    "define fact = let loop (n = 10, acc = 1)
+                   in if iszero(n) then acc
+                      else loop(-(n, 1), let i = 0 in let prod = 0
+                                         in begin
+                                              while if iszero(-(i, n)) then false else true
+                                              do begin set prod = -(prod, -(0, acc)); set i = -(i, -1) end;
+                                              prod
+                                            end)
+     fact";
+    let loop_tokens = tokenize(loop_str).unwrap();
+    match parse(&loop_tokens) {
+        Ok(v)  => {println!("{}", v);
+                   println!("\nloop_value = {:?}", value_of_program(&v, &env));},
+        Err(e) => println!("Syntax error: {:#?}", e),
+    }
+
+    println!("\nStarting to parse: modules");
+    let module_str =
+    "module counter
+       interface [start : int step : (int -> int)]
+       body [start = 33
+             offset = 1
+             step = proc (n : int) -(n, -(0, offset))]
+     module broken
+       interface [flag : bool]
+       body [flag = from counter take start]
+     let a = 10 in from counter take step(-(from counter take start, a))";
+    let module_tokens = tokenize(module_str).unwrap();
+    match parse(&module_tokens) {
+        Ok(v)  => {println!("{}", v);
+                   match type_of_program(&v, &TypeEnv::new_tenv()) {
+                       Ok(t)  => println!("\nmodule_type = {}", t),
+                       Err(e) => println!("\n{}", e),
+                   }
+                   println!("module_value = {:?}", value_of_program(&v, &env));},
+        Err(e) => println!("Syntax error: {:#?}", e),
+    }
+
+    println!("\nStarting to parse: classes");
+    let class_str =
+    "interface readable
+       method int read()
+     class counter extends object implements readable
+       field int count
+       method void initialize(start : int) set count = start
+       method counter tick() begin set count = -(count, -1); self end
+       method int read() count
+     class stepper extends counter
+       field int step
+       method void initialize(start : int, s : int) begin super initialize(start); set step = s end
+       method counter tick() begin set count = -(count, -(0, step)); self end
+     let c = new stepper(10, 5)
+     in send send send c tick() tick() read()";
+    let class_tokens = tokenize(class_str).unwrap();
+    match parse(&class_tokens) {
+        Ok(v)  => {println!("{}", v);
+                   match type_of_program(&v, &TypeEnv::new_tenv()) {
+                       Ok(t)  => println!("\nclass_type = {}", t),
+                       Err(e) => println!("\n{}", e),
+                   }
+                   println!("class_value = {:?}", value_of_program(&v, &env));},
+        Err(e) => println!("Syntax error: {:#?}", e),
+    }
+
+    println!("\nStarting to parse: effects");
+    let effect_str =
+    "let f = handle let x = perform get(0)
+                    in begin perform put(-(x, -5)); perform get(0) end
+             with {get(u, k) -> proc (s) let g = k(s) in g(s),
+                   put(v, k) -> proc (s) let g = k(v) in g(v),
+                   return(x) -> proc (s) x}
+     in f(10)";
+    let effect_tokens = tokenize(effect_str).unwrap();
+    match parse(&effect_tokens) {
+        Ok(v)  => {println!("{}", v);
+                   println!("\neffect_value = {:?}", value_of_program(&v, &env));},
+        Err(e) => println!("Syntax error: {:#?}", e),
+    }
+
+    println!("\nStarting to parse: probabilities");
+    let prob_str =
+    "let rain = flip(20)
+     in let sprinkler = if rain then flip(1) else flip(40)
+        in let wet = if rain then true else sprinkler
+           in begin observe(wet); rain end";
+    let prob_tokens = tokenize(prob_str).unwrap();
+    match parse(&prob_tokens) {
+        Ok(v)  => {println!("{}", v);
+                   match distribution_of_program(&v, &env) {
+                       Some(dist) => for (val, w) in dist {
+                                         println!("P({}) = {}", val.to_string(), w);
+                                     },
+                       None       => println!("No distribution"),
+                   }},
+        Err(e) => println!("Syntax error: {:#?}", e),
+    }
+
+    println!("\nStarting to parse: scoping");
+    let scope_str =
+    "define x = 1
+     define f = proc (y) -(y, x)
+     let x = 10 in f(20)";
+    let scope_tokens = tokenize(scope_str).unwrap();
+    match parse(&scope_tokens) {
+        Ok(v)  => {println!("{}", v);
+                   for diff in scoping_diffs(&v, &env) {
+                       println!("{}", diff);
+                   }},
+        Err(e) => println!("Syntax error: {:#?}", e),
+    }
+
+    println!("\nStarting to parse: contracts");
+    let contract_str =
+    "let pred : (positive? -> int?) = proc (n) -(n, 1)
+     in pred(-(0, 1))";
+    let contract_tokens = tokenize(contract_str).unwrap();
+    match parse(&contract_tokens) {
+        Ok(v)  => {println!("{}", v);
+                   match eval_program(&v, &env) {
+                       Ok(val) => println!("contract_value: {}", val.to_string()),
+                       Err(e)  => println!("{}", e),
+                   }},
         Err(e) => println!("Syntax error: {:#?}", e),
     }
 
     println!("\nNow testing: ungrammatical input!");
     let ungram_str =
-    "let x = 21 in minus)";
+    "let x = -(21 3) in\nlet y = 21 in minus)";
     let if_tok_result = tokenize(ungram_str);
     let if_tokens = if_tok_result.unwrap();
     let if_ast_result = parse(&if_tokens);
     match if_ast_result {
         Ok(v)  => println!("{}", v),
-        Err(e) => println!("Syntax error: {:#?}", e),
+        Err(errors) => for e in errors { println!("{}", e.render(ungram_str)) },
     }
 }